@@ -0,0 +1,85 @@
+"""Round trip a small proxy list through the Rust polish pipeline.
+
+Build the extension and run with:
+
+    maturin develop --features python
+    pytest python/
+
+The module can also be loaded from a plain `cargo build --features python`
+by copying `target/debug/librotator_rs.so` to `rotator_rs.so` on PYTHONPATH.
+"""
+
+import rotator_rs
+
+
+def make_proxy(ip, port, proto, latency=0.5, source_type="standard"):
+    return {
+        "ip": ip,
+        "port": port,
+        "type": proto,
+        "latency": latency,
+        "country": "US",
+        "anonymity": "elite",
+        "score": 0.0,
+        "source_type": source_type,
+    }
+
+
+def sample_pool():
+    return [
+        make_proxy("10.0.0.1", 1080, "socks5", 0.2),
+        # Duplicate endpoint: the premium record wins
+        make_proxy("10.0.0.1", 1080, "socks5", 0.3, source_type="premium"),
+        make_proxy("10.0.0.2", 443, "https", 1.5),
+        make_proxy("10.0.0.3", 8080, "http", 0.8),
+        make_proxy("10.0.0.4", 1080, "socks4", 0.4),
+    ]
+
+
+def test_deduplicate_keeps_premium():
+    proxies = rotator_rs.deduplicate(sample_pool())
+    keys = sorted((p["ip"], p["port"]) for p in proxies)
+    assert keys == [
+        ("10.0.0.1", 1080),
+        ("10.0.0.2", 443),
+        ("10.0.0.3", 8080),
+        ("10.0.0.4", 1080),
+    ]
+    dup = next(p for p in proxies if p["ip"] == "10.0.0.1")
+    assert dup["source_type"] == "premium"
+
+
+def test_calculate_scores_ranks_pool():
+    proxies = rotator_rs.calculate_scores(rotator_rs.deduplicate(sample_pool()))
+    scores = {p["ip"]: p["score"] for p in proxies}
+    assert all(s > 0.0 for s in scores.values())
+    assert scores["10.0.0.1"] > scores["10.0.0.2"]
+
+
+def test_calculate_scores_accepts_partial_weights():
+    pool = rotator_rs.deduplicate(sample_pool())
+    default = {p["ip"]: p["score"] for p in rotator_rs.calculate_scores(pool)}
+    tuned = {
+        p["ip"]: p["score"]
+        for p in rotator_rs.calculate_scores(pool, {"latency": 0.0})
+    }
+    assert tuned != default
+
+
+def test_split_pools_by_dns_capability():
+    proxies = rotator_rs.calculate_scores(rotator_rs.deduplicate(sample_pool()))
+    dns, non_dns = rotator_rs.split_pools(proxies)
+    assert sorted(p["type"] for p in dns) == ["https", "socks5"]
+    # SOCKS4 is dropped entirely
+    assert [p["type"] for p in non_dns] == ["http"]
+    # Records survive the round trip unchanged
+    by_ip = {p["ip"]: p for p in proxies}
+    for p in dns + non_dns:
+        assert p == by_ip[p["ip"]]
+
+
+if __name__ == "__main__":
+    for name, fn in list(globals().items()):
+        if name.startswith("test_"):
+            fn()
+    print("ok")
@@ -0,0 +1,258 @@
+//! # Spectre Affinity - Per-Target Exit Memory
+//!
+//! This module remembers how each proxy fared against the hosts clients actually
+//! request, as opposed to the verification target. The memory is a bounded LRU
+//! of `(proxy, host)` outcomes. Embedders either keep their own
+//! `TargetAffinity` and pass `exit_weights` for the requested host in
+//! `ChainOptions`, or report outcomes to the process-wide memory
+//! (`record_exit_outcome`, exposed as `record_exit_outcome_c` and the Python
+//! `record_exit_outcome`) and name the host when building a chain, so the exit
+//! hop favours proxies known to work for it.
+
+use crate::types::{endpoint_key, ChainOptions};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Upper bound on the exit weight a perfect track record can earn.
+const MAX_EXIT_WEIGHT: f64 = 2.0;
+
+/// `(proxy, host)` pairs kept by the process-wide memory.
+const SHARED_CAPACITY: usize = 4096;
+
+lazy_static::lazy_static! {
+    static ref SHARED: Mutex<TargetAffinity> = Mutex::new(TargetAffinity::new(SHARED_CAPACITY));
+}
+
+/// Record an outcome in the process-wide memory (see `TargetAffinity::record`).
+pub fn record_exit_outcome(proxy_key: &str, host: &str, ok: bool) {
+    SHARED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record(proxy_key, host, ok);
+}
+
+/// Add the process-wide memory's exit weights for `host` to
+/// `opts.exit_weights`. Weights the caller already set are kept.
+pub fn apply_exit_affinity(opts: &mut ChainOptions, host: &str) {
+    let weights = SHARED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .exit_weights(host);
+    for (proxy, weight) in weights {
+        opts.exit_weights.entry(proxy).or_insert(weight);
+    }
+}
+
+/// `proxy_key` in `Proxy::key` form, so outcomes match the rotator's
+/// `exit_weights` lookups however the caller spelled the address: an IPv6
+/// `2001:DB8::1:1080` becomes `[2001:db8::1]:1080`. Keys without a port are
+/// kept as given.
+fn canonical_proxy_key(proxy_key: &str) -> String {
+    proxy_key
+        .rsplit_once(':')
+        .and_then(|(host, port)| {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            Some(endpoint_key(host, port.parse().ok()?))
+        })
+        .unwrap_or_else(|| proxy_key.to_string())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Outcome {
+    successes: u32,
+    failures: u32,
+    last_used: u64,
+}
+
+/// Bounded LRU of `(proxy key, target host)` outcomes.
+#[derive(Debug, Clone)]
+pub struct TargetAffinity {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<(String, String), Outcome>,
+}
+
+impl TargetAffinity {
+    pub fn new(capacity: usize) -> Self {
+        TargetAffinity {
+            capacity: capacity.max(1),
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record a connection outcome through `proxy_key` (`ip:port`) to `host`.
+    /// Evicts the least recently used pair when the memory is full.
+    pub fn record(&mut self, proxy_key: &str, host: &str, ok: bool) {
+        self.tick += 1;
+        let key = (canonical_proxy_key(proxy_key), host.to_ascii_lowercase());
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, o)| o.last_used)
+                .map(|(k, _)| k.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        let outcome = self.entries.entry(key).or_default();
+        if ok {
+            outcome.successes = outcome.successes.saturating_add(1);
+        } else {
+            outcome.failures = outcome.failures.saturating_add(1);
+        }
+        outcome.last_used = self.tick;
+    }
+
+    /// Selection multiplier for `proxy_key` as an exit to `host`: 1.0 with no
+    /// history, rising towards `MAX_EXIT_WEIGHT` with successes and falling
+    /// towards 0 with failures (Laplace-smoothed success rate).
+    pub fn weight(&self, proxy_key: &str, host: &str) -> f64 {
+        let key = (canonical_proxy_key(proxy_key), host.to_ascii_lowercase());
+        match self.entries.get(&key) {
+            Some(o) => {
+                let rate =
+                    (o.successes as f64 + 1.0) / (o.successes as f64 + o.failures as f64 + 2.0);
+                rate * MAX_EXIT_WEIGHT
+            }
+            None => 1.0,
+        }
+    }
+
+    /// Exit weights for every proxy with history against `host`, ready for
+    /// `ChainOptions::exit_weights`.
+    pub fn exit_weights(&self, host: &str) -> HashMap<String, f64> {
+        let host = host.to_ascii_lowercase();
+        self.entries
+            .keys()
+            .filter(|(_, h)| *h == host)
+            .map(|(proxy, _)| (proxy.clone(), self.weight(proxy, &host)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotator::weighted_random_choice;
+    use crate::types::{ChainOptions, Proxy, ProxyTier};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn make_proxy(ip: &str, port: u16) -> Proxy {
+        Proxy {
+            ip: ip.to_string(),
+            port,
+            proto: "socks5".to_string(),
+            latency: 0.2,
+            jitter: 0.0,
+            country: "us".to_string(),
+            anonymity: "elite".to_string(),
+            score: 0.8,
+            tier: ProxyTier::Gold,
+            fail_count: 0,
+            last_verified: NOW,
+            alive: true,
+            source_type: "standard".to_string(),
+            probes_total: 0,
+            probes_ok: 0,
+            supports_connect: true,
+        }
+    }
+
+    #[test]
+    fn test_known_good_exit_preferred_for_host() {
+        let pool = vec![
+            make_proxy("192.168.1.1", 1080),
+            make_proxy("192.168.2.1", 1080),
+        ];
+
+        let mut affinity = TargetAffinity::new(64);
+        for _ in 0..10 {
+            affinity.record("192.168.1.1:1080", "example.com", true);
+            affinity.record("192.168.2.1:1080", "example.com", false);
+        }
+        // History against another host must not leak into this one
+        affinity.record("192.168.2.1:1080", "other.org", true);
+
+        let opts = ChainOptions {
+            exit_weights: affinity.exit_weights("Example.com"),
+            ..ChainOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut good_exits = 0;
+        for _ in 0..200 {
+            let picks = weighted_random_choice(&pool, &mut rng, 2, 1.0, &opts, None, NOW);
+            // The exit is the last pick
+            if picks.last().unwrap().index == 0 {
+                good_exits += 1;
+            }
+        }
+        // Expected ~92% with these weights
+        assert!(
+            good_exits > 150,
+            "good exit chosen {} of 200 times",
+            good_exits
+        );
+    }
+
+    #[test]
+    fn test_shared_memory_fills_missing_exit_weights() {
+        for _ in 0..5 {
+            record_exit_outcome("192.168.7.1:1080", "shared.example", true);
+            record_exit_outcome("192.168.8.1:1080", "shared.example", false);
+        }
+
+        let mut opts = ChainOptions::default();
+        opts.exit_weights
+            .insert("192.168.8.1:1080".to_string(), 1.5);
+        apply_exit_affinity(&mut opts, "SHARED.example");
+
+        assert!(opts.exit_weights["192.168.7.1:1080"] > 1.0);
+        // A weight the caller set is not overridden by the shared history
+        assert_eq!(opts.exit_weights["192.168.8.1:1080"], 1.5);
+    }
+
+    #[test]
+    fn test_outcomes_keyed_like_proxy_key() {
+        let mut affinity = TargetAffinity::new(8);
+        affinity.record("2001:DB8::1:1080", "example.com", true);
+        affinity.record("[2001:db8:0::1]:1080", "example.com", true);
+
+        let proxy = make_proxy("2001:db8::1", 1080);
+        assert_eq!(affinity.len(), 1);
+        assert_eq!(
+            affinity
+                .exit_weights("example.com")
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&proxy.key()]
+        );
+        assert!(affinity.weight(&proxy.key(), "example.com") > 1.0);
+    }
+
+    #[test]
+    fn test_affinity_lru_is_bounded() {
+        let mut affinity = TargetAffinity::new(2);
+        affinity.record("a:1", "x.com", true);
+        affinity.record("b:1", "x.com", true);
+        affinity.record("a:1", "x.com", true); // refresh a
+        affinity.record("c:1", "x.com", false); // evicts b
+
+        assert_eq!(affinity.len(), 2);
+        assert_eq!(affinity.weight("b:1", "x.com"), 1.0);
+        assert!(affinity.weight("a:1", "x.com") > 1.0);
+        assert!(affinity.weight("c:1", "x.com") < 1.0);
+    }
+}
@@ -15,6 +15,9 @@ use aes_gcm::{
     Aes256Gcm, Key, Nonce,
 };
 use anyhow::{Context, Result};
+use rand::RngCore;
+
+use crate::types::{CryptoHop, RotationDecision, SealedDecision};
 
 /// Derive a unique 12-byte nonce from a base nonce and a packet counter.
 ///
@@ -207,6 +210,231 @@ pub fn decrypt(key_hex: &str, data: &[u8]) -> Result<Vec<u8>> {
         .map_err(|e| anyhow::anyhow!("AES-GCM decrypt error: {}", e))
 }
 
+/// AEAD algorithms implemented by this module. All hop and at-rest encryption
+/// uses AES-256-GCM.
+pub const AEAD_ALGORITHMS: [&str; 1] = ["aes-256-gcm"];
+
+/// Largest ciphertext frame `HopCipherState::read_frame` accepts (1 MiB + tag).
+pub const MAX_FRAME_LEN: usize = (1 << 20) + 16;
+
+/// Which way a framed stream flows over a hop.
+///
+/// Both ends of a hop share one `CryptoHop` key and base nonce, so each
+/// direction gets its own nonce space: `Inbound` flips the top bit of the
+/// base nonce's first byte, which `derive_nonce` never touches. Frames with
+/// the same counter in opposite directions therefore never share a GCM
+/// (key, nonce) pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Client towards the exit
+    Outbound,
+    /// Exit back towards the client
+    Inbound,
+}
+
+impl Direction {
+    fn nonce_mask(self) -> u8 {
+        match self {
+            Direction::Outbound => 0x00,
+            Direction::Inbound => 0x80,
+        }
+    }
+}
+
+/// Per-hop cipher state for one direction of a framed stream: key, base
+/// nonce and the frame counter both ends must keep in lockstep.
+///
+/// Frames are `[len: u32 BE || ciphertext + tag]`, each sealed under
+/// `derive_nonce(nonce, counter)` with the nonce separated by `Direction`.
+/// A connection uses two states per hop: the client writes `Outbound` and
+/// reads `Inbound`, the exit the reverse. The counter advances only once a
+/// frame has been written (or read and authenticated) in full.
+///
+/// Recovery: if a write fails part-way, the peer may hold a partial frame and
+/// the two counters can no longer be reconciled. Resending under the same
+/// counter would reuse a GCM nonce, and skipping it would leave a gap, so the
+/// state is poisoned instead: every later call fails and the caller must tear
+/// down the connection and re-key (e.g. `RotationDecision::rotate_keys`).
+pub struct HopCipherState {
+    cipher: Aes256Gcm,
+    nonce: [u8; 12],
+    counter: u64,
+    poisoned: bool,
+}
+
+impl HopCipherState {
+    pub fn new(key: &[u8; 32], nonce: &[u8; 12], direction: Direction) -> Self {
+        let mut nonce = *nonce;
+        nonce[0] ^= direction.nonce_mask();
+        HopCipherState {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            nonce,
+            counter: 0,
+            poisoned: false,
+        }
+    }
+
+    pub fn from_crypto_hop(hop: &CryptoHop, direction: Direction) -> Result<Self> {
+        let key: [u8; 32] = hex::decode(&hop.key_hex)
+            .context("bad key hex")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("key must be 32 bytes"))?;
+        let nonce: [u8; 12] = hex::decode(&hop.nonce_hex)
+            .context("bad nonce hex")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("nonce must be 12 bytes"))?;
+        Ok(Self::new(&key, &nonce, direction))
+    }
+
+    /// Frames completed so far, i.e. the counter of the next frame.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Whether a failed write has made this state unusable.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    fn check_usable(&self) -> Result<()> {
+        if self.poisoned {
+            anyhow::bail!("cipher state poisoned by an incomplete frame; re-key the connection");
+        }
+        Ok(())
+    }
+
+    /// Seal `plaintext` as the next frame and write it to `w`.
+    pub fn write_frame<W: std::io::Write>(&mut self, w: &mut W, plaintext: &[u8]) -> Result<()> {
+        self.check_usable()?;
+        let nonce = derive_nonce(&self.nonce, self.counter);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|e| anyhow::anyhow!("AES-GCM encrypt error: {}", e))?;
+        if ciphertext.len() > MAX_FRAME_LEN {
+            anyhow::bail!("frame of {} bytes exceeds MAX_FRAME_LEN", ciphertext.len());
+        }
+        let mut frame = Vec::with_capacity(4 + ciphertext.len());
+        frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&ciphertext);
+
+        if let Err(e) = w.write_all(&frame).and_then(|_| w.flush()) {
+            self.poisoned = true;
+            return Err(e).context("frame write failed; connection must be torn down");
+        }
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// Read the next frame from `r` and return its plaintext. A truncated or
+    /// forged frame poisons the state, for the same reason as a failed write.
+    pub fn read_frame<R: std::io::Read>(&mut self, r: &mut R) -> Result<Vec<u8>> {
+        self.check_usable()?;
+        let result = self.read_frame_inner(r);
+        match result {
+            Ok(plaintext) => {
+                self.counter += 1;
+                Ok(plaintext)
+            }
+            Err(e) => {
+                self.poisoned = true;
+                Err(e)
+            }
+        }
+    }
+
+    fn read_frame_inner<R: std::io::Read>(&self, r: &mut R) -> Result<Vec<u8>> {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len).context("frame header read failed")?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            anyhow::bail!("frame of {} bytes exceeds MAX_FRAME_LEN", len);
+        }
+        let mut ciphertext = vec![0u8; len];
+        r.read_exact(&mut ciphertext)
+            .context("frame body read failed")?;
+        let nonce = derive_nonce(&self.nonce, self.counter);
+        self.cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|e| anyhow::anyhow!("AES-GCM decrypt error: {}", e))
+    }
+}
+
+/// Derive a 32-byte AES-256 key from a passphrase with Argon2id.
+fn passphrase_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("argon2 key derivation error: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypt a decision's key material for writing to disk.
+///
+/// The hop keys are serialized and sealed with AES-256-GCM under a key derived
+/// from `passphrase` (Argon2id, random salt). The chain_id is bound as associated
+/// data, so sealed keys cannot be transplanted onto another chain.
+pub fn seal_decision(decision: &RotationDecision, passphrase: &str) -> Result<SealedDecision> {
+    let mut salt = [0u8; 16];
+    let mut nonce_bytes = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    rand::rngs::OsRng.fill_bytes(&mut nonce_bytes);
+
+    let key_bytes = passphrase_key(passphrase, &salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes));
+    let plaintext = serde_json::to_vec(&decision.encryption)?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce_bytes),
+            aes_gcm::aead::Payload {
+                msg: &plaintext,
+                aad: decision.chain_id.as_bytes(),
+            },
+        )
+        .map_err(|e| anyhow::anyhow!("AES-GCM encrypt error: {}", e))?;
+
+    let mut public = decision.clone();
+    public.encryption.clear();
+    Ok(SealedDecision {
+        decision: public,
+        salt_hex: hex::encode(salt),
+        nonce_hex: hex::encode(nonce_bytes),
+        sealed_keys_hex: hex::encode(ciphertext),
+    })
+}
+
+/// Restore the full decision from `seal_decision` output.
+///
+/// Fails on a wrong passphrase or any tampering with the keys or chain_id.
+pub fn open_decision(sealed: &SealedDecision, passphrase: &str) -> Result<RotationDecision> {
+    let salt = hex::decode(&sealed.salt_hex).context("bad salt hex")?;
+    let nonce_bytes = hex::decode(&sealed.nonce_hex).context("bad nonce hex")?;
+    let ciphertext = hex::decode(&sealed.sealed_keys_hex).context("bad sealed keys hex")?;
+    if nonce_bytes.len() != 12 {
+        anyhow::bail!("bad nonce length: {}", nonce_bytes.len());
+    }
+
+    let key_bytes = passphrase_key(passphrase, &salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes));
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce_bytes),
+            aes_gcm::aead::Payload {
+                msg: &ciphertext,
+                aad: sealed.decision.chain_id.as_bytes(),
+            },
+        )
+        .map_err(|_| {
+            anyhow::anyhow!("failed to open decision: wrong passphrase or corrupted file")
+        })?;
+
+    let encryption: Vec<CryptoHop> =
+        serde_json::from_slice(&plaintext).context("bad sealed key material")?;
+    let mut decision = sealed.decision.clone();
+    decision.encryption = encryption;
+    Ok(decision)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -388,7 +616,7 @@ mod tests {
             .expect("Counter encryption should succeed");
 
         let mut tampered_counter = encrypted_counter.clone();
-        if tampered_counter.len() > 0 {
+        if !tampered_counter.is_empty() {
             tampered_counter[0] ^= 0xFF; // Flip bits in the ciphertext
         }
 
@@ -475,4 +703,153 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("bad nonce hex"));
     }
+
+    fn make_decision() -> RotationDecision {
+        RotationDecision {
+            mode: "phantom".to_string(),
+            timestamp: 1234567890,
+            chain_id: "sealed-chain".to_string(),
+            chain: Vec::new(),
+            avg_latency: 0.0,
+            total_latency: 0.0,
+            min_score: 0.0,
+            max_score: 0.0,
+            encryption: vec![CryptoHop {
+                key_hex: generate_test_key(),
+                nonce_hex: generate_test_nonce(),
+            }],
+            garlic: false,
+            selection: None,
+        }
+    }
+
+    /// Accepts `budget` bytes, then fails every write.
+    struct ShortWriter {
+        written: Vec<u8>,
+        budget: usize,
+    }
+
+    impl std::io::Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.budget == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::BrokenPipe,
+                    "connection reset",
+                ));
+            }
+            let n = buf.len().min(self.budget);
+            self.written.extend_from_slice(&buf[..n]);
+            self.budget -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_hop() -> CryptoHop {
+        CryptoHop {
+            key_hex: generate_test_key(),
+            nonce_hex: generate_test_nonce(),
+        }
+    }
+
+    #[test]
+    fn test_hop_cipher_frames_stay_in_lockstep() {
+        let hop = test_hop();
+        let mut tx = HopCipherState::from_crypto_hop(&hop, Direction::Outbound).unwrap();
+        let mut rx = HopCipherState::from_crypto_hop(&hop, Direction::Outbound).unwrap();
+
+        let mut wire = Vec::new();
+        tx.write_frame(&mut wire, b"first").unwrap();
+        tx.write_frame(&mut wire, b"second").unwrap();
+        assert_eq!(tx.counter(), 2);
+
+        let mut reader = wire.as_slice();
+        assert_eq!(rx.read_frame(&mut reader).unwrap(), b"first");
+        assert_eq!(rx.read_frame(&mut reader).unwrap(), b"second");
+        assert_eq!(rx.counter(), 2);
+    }
+
+    #[test]
+    fn test_hop_cipher_directions_use_distinct_nonces() {
+        let hop = test_hop();
+        let mut out_tx = HopCipherState::from_crypto_hop(&hop, Direction::Outbound).unwrap();
+        let mut in_tx = HopCipherState::from_crypto_hop(&hop, Direction::Inbound).unwrap();
+
+        // Same key, same counter, same plaintext: the ciphertexts still differ
+        let mut out_wire = Vec::new();
+        let mut in_wire = Vec::new();
+        out_tx.write_frame(&mut out_wire, b"payload").unwrap();
+        in_tx.write_frame(&mut in_wire, b"payload").unwrap();
+        assert_ne!(out_wire, in_wire);
+
+        // A frame only opens in its own direction
+        let mut wrong = HopCipherState::from_crypto_hop(&hop, Direction::Outbound).unwrap();
+        assert!(wrong.read_frame(&mut in_wire.as_slice()).is_err());
+        let mut right = HopCipherState::from_crypto_hop(&hop, Direction::Inbound).unwrap();
+        assert_eq!(
+            right.read_frame(&mut in_wire.as_slice()).unwrap(),
+            b"payload"
+        );
+    }
+
+    #[test]
+    fn test_short_write_poisons_without_advancing() {
+        let mut tx = HopCipherState::from_crypto_hop(&test_hop(), Direction::Outbound).unwrap();
+        let mut conn = ShortWriter {
+            written: Vec::new(),
+            budget: 10,
+        };
+
+        assert!(tx
+            .write_frame(&mut conn, b"this frame will not fit")
+            .is_err());
+        assert_eq!(
+            tx.counter(),
+            0,
+            "counter must not advance on a partial frame"
+        );
+        assert!(tx.is_poisoned());
+
+        // No retry under the same nonce: the state refuses further frames
+        conn.budget = usize::MAX;
+        assert!(tx.write_frame(&mut conn, b"retry").is_err());
+        assert_eq!(conn.written.len(), 10);
+        assert_eq!(tx.counter(), 0);
+    }
+
+    #[test]
+    fn test_sealed_decision_roundtrip() {
+        let decision = make_decision();
+        let sealed = seal_decision(&decision, "correct horse").expect("seal should succeed");
+
+        // Nothing secret is left in the clear
+        assert!(sealed.decision.encryption.is_empty());
+        let on_disk = serde_json::to_string(&sealed).unwrap();
+        assert!(!on_disk.contains(&decision.encryption[0].key_hex));
+
+        let opened = open_decision(&sealed, "correct horse").expect("open should succeed");
+        assert_eq!(opened.chain_id, decision.chain_id);
+        assert_eq!(opened.encryption.len(), 1);
+        assert_eq!(opened.encryption[0].key_hex, decision.encryption[0].key_hex);
+        assert_eq!(
+            opened.encryption[0].nonce_hex,
+            decision.encryption[0].nonce_hex
+        );
+    }
+
+    #[test]
+    fn test_sealed_decision_wrong_passphrase() {
+        let sealed = seal_decision(&make_decision(), "correct horse").unwrap();
+        let result = open_decision(&sealed, "battery staple");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("wrong passphrase"));
+
+        // Keys are bound to their chain_id
+        let mut moved = sealed.clone();
+        moved.decision.chain_id = "other-chain".to_string();
+        assert!(open_decision(&moved, "correct horse").is_err());
+    }
 }
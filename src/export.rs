@@ -0,0 +1,290 @@
+//! # Spectre Export - Chain Output Formats
+//!
+//! This module renders a `RotationDecision` into formats consumed by tools outside
+//! the Spectre tunnel (shell scripts, proxy clients). Exported chains carry topology
+//! only; encryption keys and nonces are never written by these renderers. It also
+//! extracts pool subsets for `--step export`.
+
+use crate::types::{ChainHop, Proxy, ProxyTier, RotationDecision};
+
+/// Format a hop as a proxy URL, bracketing IPv6 literals.
+pub fn hop_url(hop: &ChainHop) -> String {
+    if hop.ip.contains(':') {
+        format!("{}://[{}]:{}", hop.proto, hop.ip, hop.port)
+    } else {
+        format!("{}://{}:{}", hop.proto, hop.ip, hop.port)
+    }
+}
+
+/// Quote a value for POSIX shell assignment. Values made only of URL-safe
+/// characters are left bare; anything else is single-quoted.
+fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-.:/@%+,".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Render the chain as shell-assignable variables, suitable for
+/// `eval $(spectre --step rotate --format env)`:
+///
+/// ```text
+/// SPECTRE_CHAIN_ID=...
+/// SPECTRE_HOP1=socks5://1.2.3.4:1080
+/// SPECTRE_HOP2=https://5.6.7.8:443
+/// ```
+pub fn render_env(decision: &RotationDecision) -> String {
+    let mut out = format!("SPECTRE_CHAIN_ID={}\n", shell_quote(&decision.chain_id));
+    for (i, hop) in decision.chain.iter().enumerate() {
+        out.push_str(&format!(
+            "SPECTRE_HOP{}={}\n",
+            i + 1,
+            shell_quote(&hop_url(hop))
+        ));
+    }
+    out
+}
+
+/// Map a hop protocol to its proxychains-ng type. proxychains tunnels through
+/// HTTP proxies with CONNECT, so `https` hops are declared as `http`.
+fn proxychains_type(proto: &str) -> &str {
+    match proto {
+        "socks5" => "socks5",
+        "socks4" => "socks4",
+        "http" | "https" => "http",
+        other => other,
+    }
+}
+
+/// Render the chain as a proxychains.conf. `strict_chain` keeps the hop order,
+/// and `proxy_dns` resolves names through the chain:
+///
+/// ```text
+/// strict_chain
+/// proxy_dns
+///
+/// [ProxyList]
+/// socks5 1.2.3.4 1080
+/// http 5.6.7.8 443
+/// ```
+pub fn render_proxychains(decision: &RotationDecision) -> String {
+    let mut out = format!(
+        "# Spectre chain {} ({} mode)\nstrict_chain\nproxy_dns\n\n[ProxyList]\n",
+        decision.chain_id, decision.mode
+    );
+    for hop in &decision.chain {
+        out.push_str(&format!(
+            "{} {} {}\n",
+            proxychains_type(&hop.proto),
+            hop.ip,
+            hop.port
+        ));
+    }
+    out
+}
+
+/// Render only the entry hop as a single `proto://ip:port` line, for clients
+/// that accept one upstream proxy. Empty when the chain has no hops.
+pub fn render_first_hop(decision: &RotationDecision) -> String {
+    decision
+        .chain
+        .first()
+        .map(|hop| format!("{}\n", hop_url(hop)))
+        .unwrap_or_default()
+}
+
+/// Alive proxies at or above `min_tier`, optionally of one protocol
+/// (case-insensitive; `socks` matches `socks5`). Pool order is kept.
+pub fn select_proxies(pool: &[Proxy], min_tier: ProxyTier, protocol: Option<&str>) -> Vec<Proxy> {
+    let protocol = protocol.map(|p| match p.to_ascii_lowercase().as_str() {
+        "socks" => "socks5".to_string(),
+        other => other.to_string(),
+    });
+    pool.iter()
+        .filter(|p| p.alive && p.tier >= min_tier)
+        .filter(|p| {
+            protocol
+                .as_deref()
+                .is_none_or(|want| p.proto.eq_ignore_ascii_case(want))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Render proxies as plain `ip:port` lines, bracketing IPv6 literals. Empty
+/// input renders as an empty string.
+pub fn render_proxy_lines(proxies: &[Proxy]) -> String {
+    proxies
+        .iter()
+        .map(|p| {
+            if p.ip.contains(':') {
+                format!("[{}]:{}\n", p.ip, p.port)
+            } else {
+                format!("{}:{}\n", p.ip, p.port)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CryptoHop;
+
+    fn make_hop(ip: &str, port: u16, proto: &str) -> ChainHop {
+        ChainHop {
+            ip: ip.to_string(),
+            port,
+            proto: proto.to_string(),
+            country: "us".to_string(),
+            latency: 0.2,
+            score: 0.8,
+            obfuscation: None,
+        }
+    }
+
+    fn make_decision(chain: Vec<ChainHop>) -> RotationDecision {
+        let hops = chain.len();
+        let encryption = chain
+            .iter()
+            .map(|_| CryptoHop {
+                key_hex: "ab".repeat(32),
+                nonce_hex: "cd".repeat(12),
+            })
+            .collect();
+        RotationDecision {
+            mode: "high".to_string(),
+            timestamp: 1234567890,
+            chain_id: "0123456789abcdef0123456789abcdef".to_string(),
+            chain,
+            avg_latency: 0.2,
+            total_latency: 0.2 * hops as f64,
+            min_score: 0.8,
+            max_score: 0.8,
+            encryption,
+            garlic: false,
+            selection: None,
+        }
+    }
+
+    #[test]
+    fn test_render_env_assignments() {
+        let decision = make_decision(vec![
+            make_hop("1.2.3.4", 1080, "socks5"),
+            make_hop("2001:db8::1", 443, "https"),
+        ]);
+
+        let out = render_env(&decision);
+        let lines: Vec<&str> = out.lines().collect();
+
+        // One assignment per hop plus the chain id
+        assert_eq!(lines.len(), decision.chain.len() + 1);
+        for line in &lines {
+            let (name, _) = line.split_once('=').expect("Line should be NAME=value");
+            assert!(
+                name.starts_with("SPECTRE_")
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'),
+                "Invalid variable name: {}",
+                name
+            );
+        }
+
+        assert_eq!(
+            lines[0],
+            "SPECTRE_CHAIN_ID=0123456789abcdef0123456789abcdef"
+        );
+        assert_eq!(lines[1], "SPECTRE_HOP1=socks5://1.2.3.4:1080");
+        // Brackets are glob characters, so the IPv6 URL must be quoted
+        assert_eq!(lines[2], "SPECTRE_HOP2='https://[2001:db8::1]:443'");
+
+        // Key material never leaks into the export
+        assert!(!out.contains(&decision.encryption[0].key_hex));
+    }
+
+    #[test]
+    fn test_render_proxychains_config() {
+        let decision = make_decision(vec![
+            make_hop("1.2.3.4", 1080, "socks5"),
+            make_hop("5.6.7.8", 443, "https"),
+            make_hop("9.9.9.9", 8080, "http"),
+        ]);
+
+        let out = render_proxychains(&decision);
+        let list: Vec<&str> = out
+            .lines()
+            .skip_while(|l| *l != "[ProxyList]")
+            .skip(1)
+            .collect();
+        assert!(out.contains("strict_chain\n"));
+        assert_eq!(
+            list,
+            vec![
+                "socks5 1.2.3.4 1080",
+                "http 5.6.7.8 443",
+                "http 9.9.9.9 8080"
+            ]
+        );
+        assert!(!out.contains(&decision.encryption[0].key_hex));
+    }
+
+    #[test]
+    fn test_render_first_hop_only() {
+        let decision = make_decision(vec![
+            make_hop("1.2.3.4", 1080, "socks5"),
+            make_hop("5.6.7.8", 443, "https"),
+            make_hop("9.9.9.9", 8080, "http"),
+        ]);
+
+        assert_eq!(render_first_hop(&decision), "socks5://1.2.3.4:1080\n");
+        assert_eq!(render_first_hop(&make_decision(Vec::new())), "");
+    }
+
+    #[test]
+    fn test_shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("plain-value"), "plain-value");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_select_and_render_proxies() {
+        let make_proxy = |ip: &str, proto: &str, score: f64, alive: bool| -> Proxy {
+            let mut p: Proxy = serde_json::from_value(serde_json::json!({
+                "ip": ip,
+                "port": 1080,
+                "type": proto,
+                "score": score,
+            }))
+            .unwrap();
+            p.tier = ProxyTier::from_score(score);
+            p.alive = alive;
+            p
+        };
+        let pool = vec![
+            make_proxy("1.2.3.4", "socks5", 0.9, true),
+            make_proxy("2001:db8::1", "https", 0.75, true),
+            make_proxy("5.6.7.8", "socks5", 0.6, true),
+            make_proxy("9.9.9.9", "socks5", 0.95, false),
+        ];
+
+        let gold = select_proxies(&pool, ProxyTier::Gold, None);
+        assert_eq!(
+            render_proxy_lines(&gold),
+            "1.2.3.4:1080\n[2001:db8::1]:1080\n"
+        );
+
+        let socks = select_proxies(&pool, ProxyTier::Silver, Some("SOCKS"));
+        let ips: Vec<&str> = socks.iter().map(|p| p.ip.as_str()).collect();
+        assert_eq!(ips, vec!["1.2.3.4", "5.6.7.8"]);
+
+        let none = select_proxies(&pool, ProxyTier::Platinum, Some("http"));
+        assert!(none.is_empty());
+        assert_eq!(render_proxy_lines(&none), "");
+        assert_eq!("Gold".parse::<ProxyTier>(), Ok(ProxyTier::Gold));
+        assert!("shiny".parse::<ProxyTier>().is_err());
+    }
+}
@@ -20,8 +20,6 @@ use pyo3::prelude::*;
 use pyo3::types::PyDict;
 use std::ffi::{CStr, CString};
 #[cfg(feature = "python")]
-use std::fs;
-#[cfg(feature = "python")]
 use std::io;
 use std::os::raw::c_char;
 use std::panic::{self, AssertUnwindSafe};
@@ -30,30 +28,64 @@ use std::path::Path;
 #[cfg(feature = "python")]
 use std::path::PathBuf;
 
+pub mod affinity;
 pub mod crypto;
+pub mod export;
+pub mod metrics;
 pub mod polish;
 pub mod rotator;
+pub mod storage;
 pub mod types;
 
 #[cfg(feature = "python")]
 use types::Proxy;
 
+/// What this build of the crate supports, for hosts to feature-detect.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub aead_algorithms: Vec<&'static str>,
+    pub proxy_protocols: Vec<&'static str>,
+    pub socks_commands: Vec<&'static str>,
+}
+
+/// Capabilities read from the same constants the crypto and rotator code
+/// dispatch on, so the report cannot drift from what is implemented.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        aead_algorithms: crypto::AEAD_ALGORITHMS.to_vec(),
+        proxy_protocols: rotator::PROXY_PROTOCOLS.to_vec(),
+        socks_commands: rotator::SOCKS_COMMANDS.to_vec(),
+    }
+}
+
 // Helper to load files
 #[cfg(feature = "python")]
 fn load_json_array(path: &Path) -> io::Result<Vec<Proxy>> {
-    if !path.exists() {
+    let Some(path) = storage::resolve_pool_path(path) else {
         return Ok(Vec::new());
-    }
-    let raw = fs::read_to_string(path)?;
+    };
+    let path = path.as_path();
+    let raw = storage::read_pool_file(path)?;
     if raw.trim().is_empty() {
         return Ok(Vec::new());
     }
-    let proxies: Vec<Proxy> = serde_json::from_str(&raw).map_err(|e| {
+    let mut proxies = types::parse_proxy_list(&raw).map_err(|e| {
         io::Error::new(
             io::ErrorKind::InvalidData,
             format!("{}: {}", path.display(), e),
         )
     })?;
+    let before = proxies.len();
+    proxies.retain(Proxy::is_valid);
+    if proxies.len() < before {
+        log::warn!(
+            "Dropped {} invalid proxies (bad ip/port) from {}",
+            before - proxies.len(),
+            path.display()
+        );
+    }
     Ok(proxies)
 }
 
@@ -65,11 +97,21 @@ fn load_all_pools(workspace: &Path) -> io::Result<(Vec<Proxy>, Vec<Proxy>, Vec<P
     Ok((dns, non_dns, combined))
 }
 
+/// Build a chain from the workspace pool files. `options` takes the
+/// `ChainOptions` keys (missing keys keep their defaults); `target_host` adds
+/// the exit weights recorded for that host by `record_exit_outcome`.
 #[cfg(feature = "python")]
 #[pyfunction]
-#[pyo3(signature = (mode, workspace=None))]
-fn build_chain(py: Python<'_>, mode: &str, workspace: Option<&str>) -> PyResult<PyObject> {
+#[pyo3(signature = (mode, workspace=None, options=None, target_host=None))]
+fn build_chain(
+    py: Python<'_>,
+    mode: &str,
+    workspace: Option<&str>,
+    options: Option<&Bound<'_, PyAny>>,
+    target_host: Option<&str>,
+) -> PyResult<types::RotationDecision> {
     let mode = mode.to_lowercase();
+    let opts = options_from_py(py, options, target_host)?;
     let ws = workspace
         .map(PathBuf::from)
         .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
@@ -82,55 +124,122 @@ fn build_chain(py: Python<'_>, mode: &str, workspace: Option<&str>) -> PyResult<
         ))
     })?;
 
-    let decision =
-        rotator::build_chain_decision(&mode, &dns, &non_dns, &combined).ok_or_else(|| {
-            PyRuntimeError::new_err(format!("Failed to build chain for mode='{}'", mode))
-        })?;
-
-    // Convert RotationDecision -> Python dict
-    let result = PyDict::new(py);
-    result.set_item("mode", decision.mode)?;
-    result.set_item("timestamp", decision.timestamp)?;
-    result.set_item("chain_id", decision.chain_id)?;
-    result.set_item("avg_latency", decision.avg_latency)?;
-    result.set_item("min_score", decision.min_score)?;
-    result.set_item("max_score", decision.max_score)?;
-
-    // Chain hops
-    let hops = decision
-        .chain
-        .iter()
-        .enumerate()
-        .map(|(i, hop)| {
-            let d = PyDict::new(py);
-            d.set_item("index", i + 1)?;
-            d.set_item("ip", &hop.ip)?;
-            d.set_item("port", hop.port)?;
-            d.set_item("proto", &hop.proto)?;
-            d.set_item("country", &hop.country)?;
-            d.set_item("latency", hop.latency)?;
-            d.set_item("score", hop.score)?;
-            Ok(d.into())
-        })
-        .collect::<PyResult<Vec<PyObject>>>()?;
-    result.set_item("chain", hops)?;
-
-    // Encryption
-    let enc = decision
-        .encryption
-        .iter()
-        .enumerate()
-        .map(|(i, ch)| {
-            let d = PyDict::new(py);
-            d.set_item("hop", i + 1)?;
-            d.set_item("key_hex", &ch.key_hex)?;
-            d.set_item("nonce_hex", &ch.nonce_hex)?;
-            Ok(d.into())
-        })
-        .collect::<PyResult<Vec<PyObject>>>()?;
-    result.set_item("encryption", enc)?;
-
-    Ok(result.into())
+    rotator::build_chain_decision_with_options(&mode, &dns, &non_dns, &combined, &opts).map_err(
+        |e| PyRuntimeError::new_err(format!("Failed to build chain for mode='{}': {}", mode, e)),
+    )
+}
+
+/// `ChainOptions` from a dict of its keys, plus the process-wide exit weights
+/// for `target_host`.
+#[cfg(feature = "python")]
+fn options_from_py(
+    py: Python<'_>,
+    options: Option<&Bound<'_, PyAny>>,
+    target_host: Option<&str>,
+) -> PyResult<types::ChainOptions> {
+    let mut opts: types::ChainOptions = match options {
+        Some(o) => {
+            let raw: String = py.import("json")?.call_method1("dumps", (o,))?.extract()?;
+            serde_json::from_str(&raw)
+                .map_err(|e| PyValueError::new_err(format!("Invalid chain options: {}", e)))?
+        }
+        None => types::ChainOptions::default(),
+    };
+    if let Some(host) = target_host {
+        affinity::apply_exit_affinity(&mut opts, host);
+    }
+    Ok(opts)
+}
+
+/// Report whether a connection through the exit `proxy_key` (`ip:port`) to
+/// `host` succeeded, for later `target_host` lookups.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn record_exit_outcome(proxy_key: &str, host: &str, ok: bool) {
+    affinity::record_exit_outcome(proxy_key, host, ok);
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl types::RotationDecision {
+    /// Plain dict in the shape `build_chain` returned before decisions were
+    /// exposed as objects.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let result = PyDict::new(py);
+        result.set_item("mode", &self.mode)?;
+        result.set_item("timestamp", self.timestamp)?;
+        result.set_item("chain_id", &self.chain_id)?;
+        result.set_item("avg_latency", self.avg_latency)?;
+        result.set_item("total_latency", self.total_latency)?;
+        result.set_item("min_score", self.min_score)?;
+        result.set_item("max_score", self.max_score)?;
+
+        // Chain hops
+        let hops = self
+            .chain
+            .iter()
+            .enumerate()
+            .map(|(i, hop)| {
+                let d = PyDict::new(py);
+                d.set_item("index", i + 1)?;
+                d.set_item("ip", &hop.ip)?;
+                d.set_item("port", hop.port)?;
+                d.set_item("proto", &hop.proto)?;
+                d.set_item("country", &hop.country)?;
+                d.set_item("latency", hop.latency)?;
+                d.set_item("score", hop.score)?;
+                Ok(d.into())
+            })
+            .collect::<PyResult<Vec<PyObject>>>()?;
+        result.set_item("chain", hops)?;
+
+        // Encryption
+        let enc = self
+            .encryption
+            .iter()
+            .enumerate()
+            .map(|(i, ch)| {
+                let d = PyDict::new(py);
+                d.set_item("hop", i + 1)?;
+                d.set_item("key_hex", &ch.key_hex)?;
+                d.set_item("nonce_hex", &ch.nonce_hex)?;
+                Ok(d.into())
+            })
+            .collect::<PyResult<Vec<PyObject>>>()?;
+        result.set_item("encryption", enc)?;
+
+        Ok(result)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "RotationDecision(mode='{}', chain_id='{}', hops={}, avg_latency={:.3})",
+            self.mode,
+            self.chain_id,
+            self.chain.len(),
+            self.avg_latency
+        )
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl types::ChainHop {
+    fn __repr__(&self) -> String {
+        format!(
+            "ChainHop(proto='{}', ip='{}', port={}, country='{}', score={:.3})",
+            self.proto, self.ip, self.port, self.country, self.score
+        )
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl types::CryptoHop {
+    // Never echo key material into logs or tracebacks
+    fn __repr__(&self) -> String {
+        "CryptoHop(key_hex=<redacted>, nonce_hex=<redacted>)".to_string()
+    }
 }
 
 #[cfg(feature = "python")]
@@ -148,18 +257,144 @@ fn validate_mode(mode: &str) -> PyResult<()> {
     }
 }
 
+/// Like `build_chain`, but from in-memory lists of proxy dicts instead of the
+/// workspace pool files. Entries with an invalid address are dropped, as the
+/// file loaders do.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (mode, dns, non_dns, combined, options=None, target_host=None))]
+fn build_chain_from_pools(
+    py: Python<'_>,
+    mode: &str,
+    dns: &Bound<'_, PyAny>,
+    non_dns: &Bound<'_, PyAny>,
+    combined: &Bound<'_, PyAny>,
+    options: Option<&Bound<'_, PyAny>>,
+    target_host: Option<&str>,
+) -> PyResult<types::RotationDecision> {
+    validate_mode(mode)?;
+    let mode = mode.to_lowercase();
+    let opts = options_from_py(py, options, target_host)?;
+    let load = |proxies: &Bound<'_, PyAny>| -> PyResult<Vec<Proxy>> {
+        let mut proxies = proxies_from_py(py, proxies)?;
+        proxies.retain(Proxy::is_valid);
+        Ok(proxies)
+    };
+    let (dns, non_dns, combined) = (load(dns)?, load(non_dns)?, load(combined)?);
+
+    rotator::build_chain_decision_with_options(&mode, &dns, &non_dns, &combined, &opts).map_err(
+        |e| PyRuntimeError::new_err(format!("Failed to build chain for mode='{}': {}", mode, e)),
+    )
+}
+
+/// Chain build failure counts since the module was loaded, by reason.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn chain_build_failures(py: Python<'_>) -> PyResult<PyObject> {
+    let f = rotator::chain_build_failures();
+    let d = PyDict::new(py);
+    d.set_item("total", f.total)?;
+    d.set_item("empty_pool", f.empty_pool)?;
+    d.set_item("unsatisfiable", f.unsatisfiable)?;
+    Ok(d.into())
+}
+
+/// Convert a list of proxy dicts to `Proxy` records. Goes through the `json`
+/// module so the accepted keys are exactly those of the pool files.
+#[cfg(feature = "python")]
+fn proxies_from_py(py: Python<'_>, proxies: &Bound<'_, PyAny>) -> PyResult<Vec<Proxy>> {
+    let raw: String = py
+        .import("json")?
+        .call_method1("dumps", (proxies,))?
+        .extract()?;
+    serde_json::from_str(&raw)
+        .map_err(|e| PyValueError::new_err(format!("Invalid proxy list: {}", e)))
+}
+
+#[cfg(feature = "python")]
+fn proxies_to_py(py: Python<'_>, proxies: &[Proxy]) -> PyResult<PyObject> {
+    let raw = serde_json::to_string(proxies)
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to serialize proxies: {}", e)))?;
+    Ok(py.import("json")?.call_method1("loads", (raw,))?.unbind())
+}
+
+/// `polish::deduplicate_proxies` over a list of proxy dicts.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn deduplicate(py: Python<'_>, proxies: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let proxies = polish::deduplicate_proxies(proxies_from_py(py, proxies)?);
+    proxies_to_py(py, &proxies)
+}
+
+/// `polish::calculate_scores` over a list of proxy dicts. `weights` takes the
+/// `ScoringWeights` keys; missing keys keep their defaults.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(name = "calculate_scores", signature = (proxies, weights=None))]
+fn calculate_scores_py(
+    py: Python<'_>,
+    proxies: &Bound<'_, PyAny>,
+    weights: Option<&Bound<'_, PyAny>>,
+) -> PyResult<PyObject> {
+    let weights: types::ScoringWeights = match weights {
+        Some(w) => {
+            let raw: String = py.import("json")?.call_method1("dumps", (w,))?.extract()?;
+            serde_json::from_str(&raw)
+                .map_err(|e| PyValueError::new_err(format!("Invalid scoring weights: {}", e)))?
+        }
+        None => types::ScoringWeights::default(),
+    };
+    let proxies = polish::calculate_scores(proxies_from_py(py, proxies)?, &weights);
+    proxies_to_py(py, &proxies)
+}
+
+/// `polish::split_proxy_pools` over a list of proxy dicts: `(dns, non_dns)`.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn split_pools(py: Python<'_>, proxies: &Bound<'_, PyAny>) -> PyResult<(PyObject, PyObject)> {
+    let (dns, non_dns) = polish::split_proxy_pools(proxies_from_py(py, proxies)?);
+    Ok((proxies_to_py(py, &dns)?, proxies_to_py(py, &non_dns)?))
+}
+
 #[cfg(feature = "python")]
 #[pyfunction]
 fn version() -> PyResult<String> {
     Ok("rotator_rs_pyo3_v1".to_string())
 }
 
+/// Crate version and supported AEAD algorithms, proxy protocols and SOCKS
+/// commands, as a dict (see `capabilities`).
+#[cfg(feature = "python")]
+#[pyfunction(name = "capabilities")]
+fn capabilities_py(py: Python<'_>) -> PyResult<PyObject> {
+    let c = capabilities();
+    let d = PyDict::new(py);
+    d.set_item("version", c.version)?;
+    d.set_item("aead_algorithms", c.aead_algorithms)?;
+    d.set_item("proxy_protocols", c.proxy_protocols)?;
+    d.set_item("socks_commands", c.socks_commands)?;
+    Ok(d.into())
+}
+
 #[cfg(feature = "python")]
 #[pymodule]
 fn rotator_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(build_chain, m)?)?;
+    m.add_function(wrap_pyfunction!(build_chain_from_pools, m)?)?;
+    m.add_function(wrap_pyfunction!(record_exit_outcome, m)?)?;
     m.add_function(wrap_pyfunction!(validate_mode, m)?)?;
     m.add_function(wrap_pyfunction!(version, m)?)?;
+    m.add_function(wrap_pyfunction!(capabilities_py, m)?)?;
+    m.add_function(wrap_pyfunction!(chain_build_failures, m)?)?;
+    m.add_function(wrap_pyfunction!(deduplicate, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_scores_py, m)?)?;
+    m.add_function(wrap_pyfunction!(split_pools, m)?)?;
+    m.add_class::<types::RotationDecision>()?;
+    m.add_class::<types::ChainHop>()?;
+    m.add_class::<types::CryptoHop>()?;
+    m.add_class::<types::ObfuscationConfig>()?;
+    m.add_class::<types::SelectionTrace>()?;
+    m.add_class::<types::HopSelection>()?;
     Ok(())
 }
 
@@ -375,206 +610,363 @@ pub extern "C" fn run_polish_c(raw_json: *const c_char, weights_json: *const c_c
     result.unwrap_or(std::ptr::null_mut())
 }
 
+/// C API function merging freshly scraped proxies into an existing pool (both
+/// JSON arrays) with `polish::merge_pools`: duplicates keep the more recently
+/// verified record. Returns the merged array as JSON, or NULL on bad input.
 #[no_mangle]
-pub extern "C" fn build_chain_decision_c(
-    mode: *const c_char,
-    dns_json: *const c_char,
-    non_dns_json: *const c_char,
-    combined_json: *const c_char,
+pub extern "C" fn merge_pools_c(
+    existing_json: *const c_char,
+    fresh_json: *const c_char,
 ) -> *mut c_char {
-    // Initialize logger (safe to call multiple times due to try_init)
     init_logger();
-
-    // Wrap entire function in panic catch to prevent panics crossing FFI boundary
     let result = catch_unwind_ffi(
         || {
-            // Validate all pointers are non-null
-            if mode.is_null() {
-                log::error!("build_chain_decision_c: Called with null mode pointer");
-                return None;
-            }
-            if dns_json.is_null() {
-                log::error!("build_chain_decision_c: Called with null dns_json pointer");
-                return None;
-            }
-            if non_dns_json.is_null() {
-                log::error!("build_chain_decision_c: Called with null non_dns_json pointer");
-                return None;
-            }
-            if combined_json.is_null() {
-                log::error!("build_chain_decision_c: Called with null combined_json pointer");
-                return None;
-            }
-
-            // Validate and parse mode string
-            let mode_c_str = unsafe { CStr::from_ptr(mode) };
-            let mode_str = match mode_c_str.to_str() {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!(
-                        "build_chain_decision_c: Invalid UTF-8 in mode parameter: {}",
-                        e
-                    );
-                    return None;
-                }
-            };
-
-            // Validate mode is one of the allowed values
-            if !validate_mode_string(mode_str) {
-                log::error!("build_chain_decision_c: Invalid mode parameter: '{}' (allowed: lite, stealth, high, phantom)", mode_str);
+            if existing_json.is_null() || fresh_json.is_null() {
+                log::error!("merge_pools_c called with null pointer");
                 return None;
             }
-
-            // Validate and parse DNS JSON
-            let dns_c_str = unsafe { CStr::from_ptr(dns_json) };
-            let dns_str = match dns_c_str.to_str() {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!(
-                        "build_chain_decision_c: Invalid UTF-8 in dns_json parameter: {}",
-                        e
-                    );
+            let mut pools = Vec::with_capacity(2);
+            for (name, ptr) in [("existing_json", existing_json), ("fresh_json", fresh_json)] {
+                let raw = match unsafe { CStr::from_ptr(ptr) }.to_str() {
+                    Ok(s) => s,
+                    Err(e) => {
+                        log::error!("merge_pools_c: Invalid UTF-8 in {}: {}", name, e);
+                        return None;
+                    }
+                };
+                if !validate_json_array(raw) {
+                    log::error!("merge_pools_c: Invalid {} array structure", name);
                     return None;
                 }
-            };
-
-            if !validate_json_array(dns_str) {
-                log::error!("build_chain_decision_c: Invalid dns_json array structure");
-                return None;
-            }
-
-            // Validate and parse non-DNS JSON
-            let non_dns_c_str = unsafe { CStr::from_ptr(non_dns_json) };
-            let non_dns_str = match non_dns_c_str.to_str() {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!(
-                        "build_chain_decision_c: Invalid UTF-8 in non_dns_json parameter: {}",
-                        e
-                    );
-                    return None;
+                match serde_json::from_str::<Vec<types::Proxy>>(raw) {
+                    Ok(p) => pools.push(p),
+                    Err(e) => {
+                        log::error!("merge_pools_c: Failed to parse {}: {}", name, e);
+                        return None;
+                    }
                 }
-            };
-
-            if !validate_json_array(non_dns_str) {
-                log::error!("build_chain_decision_c: Invalid non_dns_json array structure");
-                return None;
             }
-
-            // Validate and parse combined JSON
-            let combined_c_str = unsafe { CStr::from_ptr(combined_json) };
-            let combined_str = match combined_c_str.to_str() {
+            let fresh = pools.pop()?;
+            let existing = pools.pop()?;
+            let merged = polish::merge_pools(existing, fresh);
+            let out_json = match serde_json::to_string(&merged) {
                 Ok(s) => s,
                 Err(e) => {
-                    log::error!(
-                        "build_chain_decision_c: Invalid UTF-8 in combined_json parameter: {}",
-                        e
-                    );
+                    log::error!("merge_pools_c: Failed to serialize merged pool: {}", e);
                     return None;
                 }
             };
+            CString::new(out_json).ok().map(CString::into_raw)
+        },
+        "merge_pools_c",
+    );
 
-            if !validate_json_array(combined_str) {
-                log::error!("build_chain_decision_c: Invalid combined_json array structure");
-                return None;
-            }
+    result.unwrap_or(std::ptr::null_mut())
+}
 
-            // Parse JSON arrays into Proxy objects
-            let dns: Vec<types::Proxy> = match serde_json::from_str(dns_str) {
-                Ok(p) => p,
-                Err(e) => {
-                    log::error!("build_chain_decision_c: Failed to parse dns_json: {} | Input preview: {:.100}", e, dns_str);
-                    return None;
-                }
-            };
+#[no_mangle]
+pub extern "C" fn build_chain_decision_c(
+    mode: *const c_char,
+    dns_json: *const c_char,
+    non_dns_json: *const c_char,
+    combined_json: *const c_char,
+) -> *mut c_char {
+    // Initialize logger (safe to call multiple times due to try_init)
+    init_logger();
 
-            let non_dns: Vec<types::Proxy> = match serde_json::from_str(non_dns_str) {
-                Ok(p) => p,
-                Err(e) => {
-                    log::error!("build_chain_decision_c: Failed to parse non_dns_json: {} | Input preview: {:.100}", e, non_dns_str);
-                    return None;
-                }
-            };
+    // Wrap entire function in panic catch to prevent panics crossing FFI boundary
+    let result = catch_unwind_ffi(
+        || {
+            chain_decision_json(
+                "build_chain_decision_c",
+                mode,
+                dns_json,
+                non_dns_json,
+                combined_json,
+                &types::ChainOptions::default(),
+            )
+        },
+        "build_chain_decision_c",
+    );
 
-            let combined: Vec<types::Proxy> = match serde_json::from_str(combined_str) {
-                Ok(p) => p,
-                Err(e) => {
-                    log::error!("build_chain_decision_c: Failed to parse combined_json: {} | Input preview: {:.100}", e, combined_str);
-                    return None;
-                }
-            };
+    // Return null pointer on panic or error
+    result.unwrap_or(std::ptr::null_mut())
+}
 
-            // Validate proxy data in all arrays
-            for (arrays_name, proxies) in [
-                ("dns", &dns),
-                ("non_dns", &non_dns),
-                ("combined", &combined),
-            ] {
-                for (i, proxy) in proxies.iter().enumerate() {
-                    if proxy.ip.is_empty() || proxy.ip.len() > 255 {
-                        log::error!(
-                            "build_chain_decision_c: Invalid IP in {} array at index {}",
-                            arrays_name,
-                            i
-                        );
+/// Like `build_chain_decision_c`, with `options_json` a `ChainOptions` object
+/// (NULL for the defaults). A non-NULL `target_host` adds the exit weights
+/// recorded for that host through `record_exit_outcome_c` to the options'
+/// `exit_weights` (entries already in the options win).
+#[no_mangle]
+pub extern "C" fn build_chain_decision_with_options_c(
+    mode: *const c_char,
+    dns_json: *const c_char,
+    non_dns_json: *const c_char,
+    combined_json: *const c_char,
+    options_json: *const c_char,
+    target_host: *const c_char,
+) -> *mut c_char {
+    init_logger();
+    let result = catch_unwind_ffi(
+        || {
+            let op = "build_chain_decision_with_options_c";
+            let mut opts = if options_json.is_null() {
+                types::ChainOptions::default()
+            } else {
+                let raw = match unsafe { CStr::from_ptr(options_json) }.to_str() {
+                    Ok(s) => s,
+                    Err(e) => {
+                        log::error!("{}: Invalid UTF-8 in options_json parameter: {}", op, e);
                         return None;
                     }
-                    if proxy.port == 0 {
-                        log::error!(
-                            "build_chain_decision_c: Invalid port in {} array at index {}",
-                            arrays_name,
-                            i
-                        );
+                };
+                match serde_json::from_str(raw) {
+                    Ok(o) => o,
+                    Err(e) => {
+                        log::error!("{}: Failed to parse options_json: {}", op, e);
                         return None;
                     }
-                    if proxy.proto.is_empty() || proxy.proto.len() > 32 {
-                        log::error!("build_chain_decision_c: Invalid protocol in {} array at index {}: IP={}, Port={}, Proto='{}'", 
-                            arrays_name, i, proxy.ip, proxy.port, proxy.proto);
+                }
+            };
+            if !target_host.is_null() {
+                match unsafe { CStr::from_ptr(target_host) }.to_str() {
+                    Ok(host) => affinity::apply_exit_affinity(&mut opts, host),
+                    Err(e) => {
+                        log::error!("{}: Invalid UTF-8 in target_host parameter: {}", op, e);
                         return None;
                     }
                 }
             }
+            chain_decision_json(op, mode, dns_json, non_dns_json, combined_json, &opts)
+        },
+        "build_chain_decision_with_options_c",
+    );
 
-            // Build the chain decision
-            let decision = match rotator::build_chain_decision(mode_str, &dns, &non_dns, &combined)
-            {
-                Some(d) => d,
-                None => {
-                    log::error!(
-                        "build_chain_decision_c: build_chain_decision returned None for mode: {}",
-                        mode_str
-                    );
-                    return None;
-                }
-            };
-
-            let out_json = match serde_json::to_string(&decision) {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!(
-                        "build_chain_decision_c: Failed to serialize chain decision: {}",
-                        e
-                    );
-                    return None;
-                }
-            };
+    result.unwrap_or(std::ptr::null_mut())
+}
 
-            match CString::new(out_json) {
-                Ok(c_string) => Some(c_string.into_raw()),
-                Err(e) => {
-                    log::error!(
-                        "build_chain_decision_c: Failed to create C string from decision: {}",
-                        e
-                    );
-                    None
-                }
+/// Record whether a connection through the exit `proxy_key` (`ip:port`) to
+/// `host` succeeded (`ok` non-zero), for later `target_host` lookups by
+/// `build_chain_decision_with_options_c`. Returns 0, or -1 on bad input.
+#[no_mangle]
+pub extern "C" fn record_exit_outcome_c(
+    proxy_key: *const c_char,
+    host: *const c_char,
+    ok: i32,
+) -> i32 {
+    init_logger();
+    let result = catch_unwind_ffi(
+        || {
+            if proxy_key.is_null() || host.is_null() {
+                log::error!("record_exit_outcome_c called with null pointer");
+                return None;
             }
+            let proxy_key = unsafe { CStr::from_ptr(proxy_key) }.to_str().ok()?;
+            let host = unsafe { CStr::from_ptr(host) }.to_str().ok()?;
+            affinity::record_exit_outcome(proxy_key, host, ok != 0);
+            Some(0)
         },
-        "build_chain_decision_c",
+        "record_exit_outcome_c",
     );
 
-    // Return null pointer on panic or error
-    result.unwrap_or(std::ptr::null_mut())
+    result.unwrap_or(-1)
+}
+
+/// Shared body of the `build_chain_decision*_c` functions: validates and
+/// parses the pool arrays, builds a decision with `opts` and returns it as a
+/// C string. `op` names the calling function in log messages.
+fn chain_decision_json(
+    op: &str,
+    mode: *const c_char,
+    dns_json: *const c_char,
+    non_dns_json: *const c_char,
+    combined_json: *const c_char,
+    opts: &types::ChainOptions,
+) -> Option<*mut c_char> {
+    // Validate all pointers are non-null
+    if mode.is_null() {
+        log::error!("{}: Called with null mode pointer", op);
+        return None;
+    }
+    if dns_json.is_null() {
+        log::error!("{}: Called with null dns_json pointer", op);
+        return None;
+    }
+    if non_dns_json.is_null() {
+        log::error!("{}: Called with null non_dns_json pointer", op);
+        return None;
+    }
+    if combined_json.is_null() {
+        log::error!("{}: Called with null combined_json pointer", op);
+        return None;
+    }
+
+    // Validate and parse mode string
+    let mode_c_str = unsafe { CStr::from_ptr(mode) };
+    let mode_str = match mode_c_str.to_str() {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("{}: Invalid UTF-8 in mode parameter: {}", op, e);
+            return None;
+        }
+    };
+
+    // Validate mode is one of the allowed values
+    if !validate_mode_string(mode_str) {
+        log::error!(
+            "{}: Invalid mode parameter: '{}' (allowed: lite, stealth, high, phantom)",
+            op,
+            mode_str
+        );
+        return None;
+    }
+
+    // Validate and parse DNS JSON
+    let dns_c_str = unsafe { CStr::from_ptr(dns_json) };
+    let dns_str = match dns_c_str.to_str() {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("{}: Invalid UTF-8 in dns_json parameter: {}", op, e);
+            return None;
+        }
+    };
+
+    if !validate_json_array(dns_str) {
+        log::error!("{}: Invalid dns_json array structure", op);
+        return None;
+    }
+
+    // Validate and parse non-DNS JSON
+    let non_dns_c_str = unsafe { CStr::from_ptr(non_dns_json) };
+    let non_dns_str = match non_dns_c_str.to_str() {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("{}: Invalid UTF-8 in non_dns_json parameter: {}", op, e);
+            return None;
+        }
+    };
+
+    if !validate_json_array(non_dns_str) {
+        log::error!("{}: Invalid non_dns_json array structure", op);
+        return None;
+    }
+
+    // Validate and parse combined JSON
+    let combined_c_str = unsafe { CStr::from_ptr(combined_json) };
+    let combined_str = match combined_c_str.to_str() {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("{}: Invalid UTF-8 in combined_json parameter: {}", op, e);
+            return None;
+        }
+    };
+
+    if !validate_json_array(combined_str) {
+        log::error!("{}: Invalid combined_json array structure", op);
+        return None;
+    }
+
+    // Parse JSON arrays into Proxy objects
+    let dns: Vec<types::Proxy> = match serde_json::from_str(dns_str) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!(
+                "{}: Failed to parse dns_json: {} | Input preview: {:.100}",
+                op,
+                e,
+                dns_str
+            );
+            return None;
+        }
+    };
+
+    let non_dns: Vec<types::Proxy> = match serde_json::from_str(non_dns_str) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!(
+                "{}: Failed to parse non_dns_json: {} | Input preview: {:.100}",
+                op,
+                e,
+                non_dns_str
+            );
+            return None;
+        }
+    };
+
+    let combined: Vec<types::Proxy> = match serde_json::from_str(combined_str) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!(
+                "{}: Failed to parse combined_json: {} | Input preview: {:.100}",
+                op,
+                e,
+                combined_str
+            );
+            return None;
+        }
+    };
+
+    // Validate proxy data in all arrays
+    for (arrays_name, proxies) in [
+        ("dns", &dns),
+        ("non_dns", &non_dns),
+        ("combined", &combined),
+    ] {
+        for (i, proxy) in proxies.iter().enumerate() {
+            if proxy.ip.is_empty() || proxy.ip.len() > 255 {
+                log::error!("{}: Invalid IP in {} array at index {}", op, arrays_name, i);
+                return None;
+            }
+            if proxy.port == 0 {
+                log::error!(
+                    "{}: Invalid port in {} array at index {}",
+                    op,
+                    arrays_name,
+                    i
+                );
+                return None;
+            }
+            if proxy.proto.is_empty() || proxy.proto.len() > 32 {
+                log::error!(
+                    "{}: Invalid protocol in {} array at index {}: IP={}, Port={}, Proto='{}'",
+                    op,
+                    arrays_name,
+                    i,
+                    proxy.ip,
+                    proxy.port,
+                    proxy.proto
+                );
+                return None;
+            }
+        }
+    }
+
+    // Build the chain decision
+    let decision =
+        match rotator::build_chain_decision_with_options(mode_str, &dns, &non_dns, &combined, opts)
+        {
+            Ok(d) => d,
+            Err(e) => {
+                log::error!("{}: {}", op, e);
+                return None;
+            }
+        };
+
+    let out_json = match serde_json::to_string(&decision) {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("{}: Failed to serialize chain decision: {}", op, e);
+            return None;
+        }
+    };
+
+    match CString::new(out_json) {
+        Ok(c_string) => Some(c_string.into_raw()),
+        Err(e) => {
+            log::error!("{}: Failed to create C string from decision: {}", op, e);
+            None
+        }
+    }
 }
 
 #[no_mangle]
@@ -769,12 +1161,9 @@ pub extern "C" fn build_chain_topology_c(
             // Build the chain decision
             let decision = match rotator::build_chain_decision(mode_str, &dns, &non_dns, &combined)
             {
-                Some(d) => d,
-                None => {
-                    log::error!(
-                        "build_chain_topology_c: build_chain_decision returned None for mode: {}",
-                        mode_str
-                    );
+                Ok(d) => d,
+                Err(e) => {
+                    log::error!("build_chain_topology_c: {}", e);
                     return None;
                 }
             };
@@ -898,6 +1287,139 @@ pub extern "C" fn derive_keys_from_secret_c(
     result.unwrap_or(std::ptr::null_mut())
 }
 
+/// C API function re-keying a decision in place (`RotationDecision::rotate_keys`):
+/// takes decision JSON and returns it with a fresh key/nonce per hop and a
+/// bumped timestamp, keeping the chain. A non-zero `new_chain_id` also mints a
+/// new chain id. Returns NULL on bad input.
+#[no_mangle]
+pub extern "C" fn rotate_keys_c(decision_json: *const c_char, new_chain_id: i32) -> *mut c_char {
+    init_logger();
+    let result = catch_unwind_ffi(
+        || {
+            if decision_json.is_null() {
+                log::error!("rotate_keys_c called with null pointer");
+                return None;
+            }
+            let raw = match unsafe { CStr::from_ptr(decision_json) }.to_str() {
+                Ok(s) => s,
+                Err(e) => {
+                    log::error!("rotate_keys_c: Invalid UTF-8 in decision_json: {}", e);
+                    return None;
+                }
+            };
+            let mut decision: types::RotationDecision = match serde_json::from_str(raw) {
+                Ok(d) => d,
+                Err(e) => {
+                    log::error!("rotate_keys_c: Failed to parse decision: {}", e);
+                    return None;
+                }
+            };
+            if let Err(e) = decision.validate() {
+                log::error!("rotate_keys_c: Invalid decision: {}", e);
+                return None;
+            }
+            decision.rotate_keys(new_chain_id != 0);
+            let out_json = match serde_json::to_string(&decision) {
+                Ok(s) => s,
+                Err(e) => {
+                    log::error!("rotate_keys_c: Failed to serialize decision: {}", e);
+                    return None;
+                }
+            };
+            CString::new(out_json).ok().map(CString::into_raw)
+        },
+        "rotate_keys_c",
+    );
+
+    result.unwrap_or(std::ptr::null_mut())
+}
+
+/// C API function returning the chain build failure counters as JSON:
+/// `{"total": n, "empty_pool": n, "unsatisfiable": n}`.
+#[no_mangle]
+pub extern "C" fn chain_build_failures_c() -> *mut c_char {
+    init_logger();
+    let result = catch_unwind_ffi(
+        || {
+            let out_json = match serde_json::to_string(&rotator::chain_build_failures()) {
+                Ok(s) => s,
+                Err(e) => {
+                    log::error!(
+                        "chain_build_failures_c: Failed to serialize counters: {}",
+                        e
+                    );
+                    return None;
+                }
+            };
+            CString::new(out_json).ok().map(CString::into_raw)
+        },
+        "chain_build_failures_c",
+    );
+
+    result.unwrap_or(std::ptr::null_mut())
+}
+
+/// C API function classifying a proxy's anonymity from probe evidence.
+/// `headers_json` is the request header map the judge endpoint received, as
+/// `{"Name": ["value", ...]}` (Go's `http.Header` marshals to this);
+/// `client_ip` is the prober's public IP. Returns `"elite"`, `"anonymous"` or
+/// `"transparent"` (see `polish::classify_anonymity`), or NULL on bad input.
+#[no_mangle]
+pub extern "C" fn classify_anonymity_c(
+    headers_json: *const c_char,
+    client_ip: *const c_char,
+) -> *mut c_char {
+    init_logger();
+    let result = catch_unwind_ffi(
+        || {
+            if headers_json.is_null() || client_ip.is_null() {
+                log::error!("classify_anonymity_c called with null pointer");
+                return None;
+            }
+            let headers_str = unsafe { CStr::from_ptr(headers_json) }.to_str().ok()?;
+            let client_ip = unsafe { CStr::from_ptr(client_ip) }.to_str().ok()?;
+            let headers: std::collections::HashMap<String, Vec<String>> =
+                match serde_json::from_str(headers_str) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        log::error!("classify_anonymity_c: Invalid header JSON: {}", e);
+                        return None;
+                    }
+                };
+            let pairs = headers
+                .iter()
+                .flat_map(|(name, values)| values.iter().map(move |v| (name.as_str(), v.as_str())));
+            let label = polish::classify_anonymity(pairs, client_ip);
+            CString::new(label).ok().map(CString::into_raw)
+        },
+        "classify_anonymity_c",
+    );
+
+    result.unwrap_or(std::ptr::null_mut())
+}
+
+/// C API function classifying an HTTP proxy's raw reply to the CONNECT probe
+/// (`polish::connect_probe_request`). Returns 1 if it tunnels, 0 if it is
+/// GET-only (405/501) and -1 when the reply is inconclusive or input is bad,
+/// in which case `supports_connect` should be left as it was.
+#[no_mangle]
+pub extern "C" fn classify_connect_c(response: *const c_char) -> i32 {
+    init_logger();
+    let result = catch_unwind_ffi(
+        || {
+            if response.is_null() {
+                log::error!("classify_connect_c called with null pointer");
+                return None;
+            }
+            let response = unsafe { CStr::from_ptr(response) }.to_string_lossy();
+            polish::classify_connect_response(&response).map(i32::from)
+        },
+        "classify_connect_c",
+    );
+
+    result.unwrap_or(-1)
+}
+
 #[no_mangle]
 pub extern "C" fn encrypt_with_counter_c(
     key_hex: *const c_char,
@@ -1052,7 +1574,7 @@ pub extern "C" fn decrypt_layered_c(
 pub extern "C" fn free_byte_array(ptr: *mut u8, len: usize) {
     if !ptr.is_null() {
         unsafe {
-            let _ = Box::from_raw(std::slice::from_raw_parts_mut(ptr, len));
+            let _ = Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len));
         }
     }
 }
@@ -1,11 +1,21 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use log::{error, info};
-use rotator_rs::types::{Proxy, RotationDecision, ScoringWeights};
-use rotator_rs::{polish, rotator};
+use log::{error, info, warn};
+use rotator_rs::polish::Blocklist;
+use rotator_rs::types::{
+    endpoint_key, parse_proxy_list, parse_scraper_output, ChainOptions, ChainTopology, HopInfo,
+    Proxy, ProxyTier, RotationDecision, ScoringWeights, SealedDecision,
+    DEFAULT_DECAY_HALF_LIFE_SECS,
+};
+use rotator_rs::{crypto, export, polish, rotator, storage};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{Deserializer as _, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 fn init_logging() {
@@ -44,12 +54,197 @@ struct Cli {
     #[arg(long)]
     stats: bool,
 
+    /// Print the crate version and supported ciphers, proxy protocols and
+    /// SOCKS commands as JSON, then exit
+    #[arg(long)]
+    capabilities: bool,
+
     #[arg(long, default_value_t = 1080)]
     port: u16,
 
     /// Skip pool re-verification and always scrape fresh proxies
     #[arg(long)]
     force_scrape: bool,
+
+    /// Build a plain chain without per-hop encryption keys
+    #[arg(long)]
+    no_encryption: bool,
+
+    /// Output format for the chain decision: json, env or proxychains
+    #[arg(long, alias = "emit", default_value = "json")]
+    format: String,
+
+    /// Write the chain decision (or `--step export` subset) to this file
+    /// instead of stdout
+    #[arg(long, alias = "out")]
+    output: Option<PathBuf>,
+
+    /// Decision file to load for `--step open`
+    #[arg(long)]
+    input: Option<PathBuf>,
+
+    /// Encrypt key material in decision files at rest (argon2 + AES-GCM).
+    /// Falls back to the SPECTRE_DECISION_PASSPHRASE environment variable.
+    #[arg(long)]
+    decision_passphrase: Option<String>,
+
+    /// Fixed exits to cycle round-robin, e.g. "ip:port,socks5://ip:port"
+    #[arg(long)]
+    exit_set: Option<String>,
+
+    /// Print only the entry hop as proto://ip:port (overrides --format)
+    #[arg(long)]
+    first_hop_only: bool,
+
+    /// Print why each hop was chosen (to stderr, alongside the decision)
+    #[arg(long)]
+    explain: bool,
+
+    /// Reuse a saved decision file verbatim (keys and all) instead of building
+    /// a new chain, to reproduce a failing chain while debugging
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Load only the top N proxies of each pool file for rotation
+    #[arg(long)]
+    load_limit: Option<usize>,
+
+    /// Read a single pool file and split it by protocol in memory, instead of
+    /// the proxies_{dns,non_dns,combined}.json trio
+    #[arg(long)]
+    pool_file: Option<PathBuf>,
+
+    /// Plaintext proxy list (ip:port, proto://ip:port, ip:port:user:pass) to
+    /// add to the polish input, e.g. to seed a pool without the scraper
+    #[arg(long)]
+    import_file: Option<PathBuf>,
+
+    /// Like --import-file, but fetch the list over http(s) (needs curl)
+    #[arg(long)]
+    import_url: Option<String>,
+
+    /// When the requested mode cannot build a chain, step down through the
+    /// weaker modes (phantom, high, stealth, lite) instead of failing
+    #[arg(long)]
+    allow_degrade: bool,
+
+    /// During polish, penalize every proxy on a host exposing more than this
+    /// many proxy ports (likely a honeypot)
+    #[arg(long)]
+    max_ports_per_ip: Option<usize>,
+
+    /// Guarantee no network I/O: steps that need it (scrape, full) are refused,
+    /// and rotate leaves last_chain.json untouched
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Protocol for --import-file/--import-url lines without a proto:// prefix
+    #[arg(long, default_value = "http")]
+    import_proto: String,
+
+    /// Only build chains from proxies in these countries (repeatable or
+    /// comma-separated, case-insensitive)
+    #[arg(long = "include-country", value_delimiter = ',')]
+    include_countries: Vec<String>,
+
+    /// Never use proxies in these countries (repeatable or comma-separated);
+    /// wins over --include-country
+    #[arg(long = "exclude-country", value_delimiter = ',')]
+    exclude_countries: Vec<String>,
+
+    /// Also write polished pools zstd-compressed (proxies_*.json.zst). The
+    /// plain files are kept, since the Go orchestrator cannot read `.zst`;
+    /// Rust loaders read either form regardless of this flag.
+    #[arg(long)]
+    compress: bool,
+
+    /// For `--step export`: keep only alive proxies at or above this tier
+    /// (platinum, gold, silver, bronze, dead)
+    #[arg(long)]
+    min_tier: Option<ProxyTier>,
+
+    /// CIDR ranges and IPs never to route through, one per line with `#`
+    /// comments (default: blocklist.txt in the workspace, if present)
+    #[arg(long)]
+    blocklist: Option<PathBuf>,
+
+    /// For `--step polish` and `full`: merge the new proxies into the existing
+    /// combined pool instead of replacing it, keeping each proxy's most
+    /// recently verified record (see `polish::merge_pools`)
+    #[arg(long)]
+    merge_pool: bool,
+
+    /// Retries when the Go scraper exits non-zero or prints nothing; 0 runs it
+    /// once, as before
+    #[arg(long, default_value_t = 2)]
+    scrape_retries: u32,
+
+    /// Delay before the first scraper retry, doubled on each further attempt
+    #[arg(long, default_value_t = 2000)]
+    scrape_retry_delay_ms: u64,
+
+    /// The target needs a CONNECT tunnel (e.g. HTTPS): never pick a GET-only
+    /// HTTP proxy as the exit
+    #[arg(long)]
+    require_connect: bool,
+
+    /// At least this many hops must resolve DNS remotely (capped at the chain
+    /// length)
+    #[arg(long, default_value_t = 0)]
+    min_dns_hops: usize,
+
+    /// Per-mode minimum effective score for every hop, e.g. "phantom=0.6"
+    /// (repeatable or comma-separated)
+    #[arg(long = "mode-floor", value_delimiter = ',', value_parser = parse_mode_floor)]
+    mode_floors: Vec<(String, f64)>,
+
+    /// Per-mode allowed proxy ports, e.g. "phantom=443,1080" (repeatable, one
+    /// mode per flag); modes without one accept any port
+    #[arg(long = "mode-ports", value_parser = parse_mode_ports)]
+    mode_ports: Vec<(String, Vec<u16>)>,
+
+    /// Half-life of the age decay applied to proxy scores during selection,
+    /// in seconds since last verification (0 disables decay)
+    #[arg(long, default_value_t = DEFAULT_DECAY_HALF_LIFE_SECS)]
+    decay_half_life_secs: u64,
+}
+
+/// `mode=value` for the per-mode flags, with the mode checked.
+fn split_mode_arg(s: &str) -> Result<(String, &str), String> {
+    let (mode, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected mode=value, got '{}'", s))?;
+    let mode = mode.trim().to_lowercase();
+    if !["lite", "stealth", "high", "phantom"].contains(&mode.as_str()) {
+        return Err(format!(
+            "unknown mode '{}' (allowed: lite, stealth, high, phantom)",
+            mode
+        ));
+    }
+    Ok((mode, value.trim()))
+}
+
+fn parse_mode_floor(s: &str) -> Result<(String, f64), String> {
+    let (mode, value) = split_mode_arg(s)?;
+    let floor: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid score '{}'", value))?;
+    if !(0.0..=1.0).contains(&floor) {
+        return Err(format!("score {} is outside 0.0-1.0", floor));
+    }
+    Ok((mode, floor))
+}
+
+fn parse_mode_ports(s: &str) -> Result<(String, Vec<u16>), String> {
+    let (mode, value) = split_mode_arg(s)?;
+    let ports = value
+        .split(',')
+        .map(|p| match p.trim().parse::<u16>() {
+            Ok(port) if port != 0 => Ok(port),
+            _ => Err(format!("invalid port '{}'", p.trim())),
+        })
+        .collect::<Result<Vec<u16>, String>>()?;
+    Ok((mode, ports))
 }
 
 fn main() -> Result<()> {
@@ -57,38 +252,219 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
     let workspace = std::env::current_dir()?;
+    let format = if cli.first_hop_only {
+        "first-hop"
+    } else {
+        cli.format.as_str()
+    };
+    let passphrase = cli
+        .decision_passphrase
+        .clone()
+        .or_else(|| std::env::var("SPECTRE_DECISION_PASSPHRASE").ok());
+    let previous_hops = load_previous_hops(&workspace);
+    let blocklist = load_blocklist(&workspace, cli.blocklist.as_deref())?;
+    let exit_set = match &cli.exit_set {
+        Some(spec) => unblocked_exits(
+            rotator::parse_exit_set(spec).context("Invalid --exit-set")?,
+            &blocklist,
+        )?,
+        None => Vec::new(),
+    };
+    // The previous chain's exit is always its last hop
+    let exit_index = rotator::next_exit_index(&exit_set, previous_hops.last().map(String::as_str));
+    let chain_opts = ChainOptions {
+        with_encryption: !cli.no_encryption,
+        previous_hops,
+        exit_set,
+        exit_index,
+        include_countries: cli.include_countries.clone(),
+        exclude_countries: cli.exclude_countries.clone(),
+        require_connect: cli.require_connect,
+        min_dns_hops: cli.min_dns_hops,
+        mode_score_floors: cli.mode_floors.iter().cloned().collect(),
+        mode_ports: cli.mode_ports.iter().cloned().collect(),
+        scoring: ScoringWeights {
+            decay_half_life_secs: cli.decay_half_life_secs,
+            ..ScoringWeights::default()
+        },
+        ..ChainOptions::default()
+    };
+
+    let polish_opts = PolishOptions {
+        max_ports_per_ip: cli.max_ports_per_ip,
+        compress: cli.compress,
+        merge_pool: cli.merge_pool,
+    };
+    let scrape_retry = ScrapeRetry {
+        retries: cli.scrape_retries,
+        base_delay: Duration::from_millis(cli.scrape_retry_delay_ms),
+    };
+
+    let build_chain = |dns: &[Proxy], non_dns: &[Proxy], combined: &[Proxy]| {
+        if cli.allow_degrade {
+            rotator::build_chain_decision_degrading(&cli.mode, dns, non_dns, combined, &chain_opts)
+        } else {
+            rotator::build_chain_decision_with_options(
+                &cli.mode,
+                dns,
+                non_dns,
+                combined,
+                &chain_opts,
+            )
+        }
+    };
+
+    if cli.capabilities {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&rotator_rs::capabilities())?
+        );
+        return Ok(());
+    }
 
     if cli.stats {
-        print_stats(&workspace)?;
+        print_stats(&workspace, cli.pool_file.as_deref(), &blocklist)?;
         return Ok(());
     }
 
+    if cli.dry_run && step_needs_network(&cli.step) {
+        anyhow::bail!(
+            "--step {} runs the scraper and needs network access; it cannot run with --dry-run",
+            cli.step
+        );
+    }
+    if cli.dry_run && cli.import_url.is_some() {
+        anyhow::bail!("--import-url fetches over the network; it cannot run with --dry-run");
+    }
+
     match cli.step.as_str() {
         "scrape" => {
-            run_scraper(&workspace, cli.limit, &cli.protocol)?;
+            run_scraper(&workspace, cli.limit, &cli.protocol, &scrape_retry)?;
         }
         "polish" => {
-            let raw = load_proxies(&workspace.join("raw_proxies.json"))?;
-            run_polish(&workspace, raw)?;
+            let mut raw = load_proxies(&workspace.join("raw_proxies.json"))?;
+            if let Some(path) = &cli.import_file {
+                raw.extend(import_proxy_list(path, &cli.import_proto)?);
+            }
+            if let Some(url) = &cli.import_url {
+                raw.extend(fetch_proxy_list(url, &cli.import_proto)?);
+            }
+            run_polish(&workspace, raw, &polish_opts)?;
         }
         "rotate" => {
-            let (dns, non_dns, combined) = load_pools(&workspace)?;
-            let decision = rotator::build_chain_decision(&cli.mode, &dns, &non_dns, &combined);
-            if let Some(d) = decision {
-                print_decision(&d);
-            } else {
-                error!("Failed to build chain");
+            // A replay never touches the pools, so it must not depend on them
+            if let Some(path) = &cli.replay {
+                let d = load_replay(path, passphrase.as_deref())?;
+                if !cli.dry_run {
+                    save_last_chain(&workspace, &d)?;
+                }
+                write_decision(&d, format, cli.output.as_deref(), passphrase.as_deref())?;
+                return Ok(());
+            }
+            let (dns, non_dns, combined) = load_pools(
+                &workspace,
+                cli.pool_file.as_deref(),
+                cli.load_limit,
+                &blocklist,
+            )?;
+            let decision = build_chain(&dns, &non_dns, &combined).or_else(|e| {
+                let fallback = load_known_good(&workspace)
+                    .and_then(|t| rotator::decision_from_known_good(&t, &combined, &chain_opts));
+                match fallback {
+                    Some(d) => {
+                        warn!(
+                            "Fresh rotation failed ({}), falling back to known-good chain {}",
+                            e, d.chain_id
+                        );
+                        Ok(d)
+                    }
+                    None => Err(e),
+                }
+            });
+            match decision {
+                Ok(d) => {
+                    rotator::log_decision(&d);
+                    if !cli.dry_run {
+                        save_last_chain(&workspace, &d)?;
+                    }
+                    write_decision(&d, format, cli.output.as_deref(), passphrase.as_deref())?;
+                    if cli.explain {
+                        print_explanation(&d, &dns, &non_dns, &combined);
+                    }
+                }
+                Err(e) => error!("Failed to build chain: {}", e),
+            }
+        }
+        "open" => {
+            let path = cli
+                .input
+                .as_deref()
+                .context("--step open requires --input <decision file>")?;
+            let d = load_decision_file(path, passphrase.as_deref())?;
+            print_decision(&d, format)?;
+        }
+        "mark-good" => {
+            mark_known_good(&workspace)?;
+        }
+        "export" => {
+            let (_, _, combined) =
+                load_pools(&workspace, cli.pool_file.as_deref(), None, &blocklist)?;
+            let protocol = Some(cli.protocol.as_str()).filter(|p| *p != "all");
+            let subset = export::select_proxies(
+                &combined,
+                cli.min_tier.unwrap_or(ProxyTier::Dead),
+                protocol,
+            );
+            let content = match format {
+                "json" => serde_json::to_string_pretty(&subset)?,
+                "plain" => export::render_proxy_lines(&subset),
+                other => anyhow::bail!(
+                    "--step export supports --format json or plain (got {})",
+                    other
+                ),
+            };
+            match &cli.output {
+                Some(path) => {
+                    fs::write(path, content)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    info!("Exported {} proxies to {}", subset.len(), path.display());
+                }
+                None => print!("{}", content),
             }
         }
+        "validate" => {
+            let files = match &cli.pool_file {
+                Some(path) => vec![path.clone()],
+                None => [
+                    "proxies_dns.json",
+                    "proxies_non_dns.json",
+                    "proxies_combined.json",
+                ]
+                .iter()
+                .map(|name| workspace.join(name))
+                .collect(),
+            };
+            validate_pool_files(&files)?;
+        }
         "full" => {
-            let raw = run_scraper(&workspace, cli.limit, &cli.protocol)?;
-            let (dns, non_dns, combined) = run_polish(&workspace, raw)?;
-            let decision = rotator::build_chain_decision(&cli.mode, &dns, &non_dns, &combined);
-
-            if let Some(d) = decision {
-                print_decision(&d);
-            } else {
-                error!("Failed to build chain");
+            let mut raw = run_scraper(&workspace, cli.limit, &cli.protocol, &scrape_retry)?;
+            if let Some(path) = &cli.import_file {
+                raw.extend(import_proxy_list(path, &cli.import_proto)?);
+            }
+            if let Some(url) = &cli.import_url {
+                raw.extend(fetch_proxy_list(url, &cli.import_proto)?);
+            }
+            let (dns, non_dns, combined) =
+                polish_for_chain(&workspace, raw, &polish_opts, &blocklist)?;
+            let decision = build_chain(&dns, &non_dns, &combined);
+
+            match decision {
+                Ok(d) => {
+                    rotator::log_decision(&d);
+                    save_last_chain(&workspace, &d)?;
+                    write_decision(&d, format, cli.output.as_deref(), passphrase.as_deref())?;
+                }
+                Err(e) => error!("Failed to build chain: {}", e),
             }
 
             // Print summary
@@ -102,7 +478,38 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run_scraper(workspace: &PathBuf, limit: usize, protocol: &str) -> Result<Vec<Proxy>> {
+/// Steps that reach the network. Everything else in this binary is file I/O and
+/// in-memory pipeline work; verification and serving live in the Go orchestrator.
+fn step_needs_network(step: &str) -> bool {
+    matches!(step, "scrape" | "full")
+}
+
+/// Cap on the delay between scraper attempts, however many retries are allowed.
+const MAX_SCRAPE_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How `run_scraper` retries a scraper run that failed or came back empty.
+struct ScrapeRetry {
+    retries: u32,
+    base_delay: Duration,
+}
+
+impl ScrapeRetry {
+    /// Delay before retry number `attempt` (0-based): `base_delay * 2^attempt`,
+    /// capped at `MAX_SCRAPE_BACKOFF`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(MAX_SCRAPE_BACKOFF, |d| d.min(MAX_SCRAPE_BACKOFF))
+    }
+}
+
+fn run_scraper(
+    workspace: &Path,
+    limit: usize,
+    protocol: &str,
+    retry: &ScrapeRetry,
+) -> Result<Vec<Proxy>> {
     // Note: This Rust standalone binary calls the Go scraper as a subprocess.
     // The primary Go orchestrator (orchestrator.go + scraper.go) has the scraper
     // compiled in and does not require a separate binary.
@@ -114,26 +521,52 @@ fn run_scraper(workspace: &PathBuf, limit: usize, protocol: &str) -> Result<Vec<
         anyhow::bail!("go_scraper binary not found at {}. Build with: go build -o go_scraper scraper.go", scraper_path.display());
     }
 
-    let output = Command::new(&scraper_path)
-        .arg("--limit")
-        .arg(limit.to_string())
-        .arg("--protocol")
-        .arg(protocol)
-        .output()
-        .context("Failed to execute go_scraper")?;
+    // Only a failed run or an empty output is retried; a populated output that
+    // fails to parse is a scraper bug, not a transient hiccup.
+    let mut attempt = 0;
+    let raw_json = loop {
+        info!("Go scraper attempt {}/{}", attempt + 1, retry.retries + 1);
+        let output = Command::new(&scraper_path)
+            .arg("--limit")
+            .arg(limit.to_string())
+            .arg("--protocol")
+            .arg(protocol)
+            .output()
+            .context("Failed to execute go_scraper")?;
 
-    if !output.status.success() {
-        error!(
-            "Go scraper stderr: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-        anyhow::bail!(
-            "Go scraper failed with exit code: {:?}",
-            output.status.code()
+        let failure = if output.status.success() {
+            None
+        } else {
+            error!(
+                "Go scraper stderr: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            Some(format!(
+                "Go scraper failed with exit code: {:?}",
+                output.status.code()
+            ))
+        };
+        let raw_json = String::from_utf8(output.stdout)?;
+        if failure.is_none() && !raw_json.trim().is_empty() {
+            break raw_json;
+        }
+        if attempt >= retry.retries {
+            match failure {
+                Some(msg) => anyhow::bail!(msg),
+                None => break raw_json,
+            }
+        }
+        let delay = retry.backoff(attempt);
+        warn!(
+            "{}; retrying in {:?}",
+            failure
+                .as_deref()
+                .unwrap_or("Go scraper returned empty output"),
+            delay
         );
-    }
-
-    let raw_json = String::from_utf8(output.stdout)?;
+        thread::sleep(delay);
+        attempt += 1;
+    };
 
     // Check if empty
     if raw_json.trim().is_empty() {
@@ -141,67 +574,480 @@ fn run_scraper(workspace: &PathBuf, limit: usize, protocol: &str) -> Result<Vec<
         return Ok(Vec::new());
     }
 
-    // Save raw
-    fs::write(workspace.join("raw_proxies.json"), &raw_json)?;
-
     // Parse
-    let proxies: Vec<Proxy> =
-        serde_json::from_str(&raw_json).context("Failed to parse go_scraper output")?;
+    let (proxies, skipped) =
+        parse_scraper_output(&raw_json).context("Failed to parse go_scraper output")?;
+    if skipped > 0 {
+        warn!("Skipped {} unparseable go_scraper output lines", skipped);
+    }
+
+    // Save raw, normalized to the array shape `--step polish` reads
+    fs::write(
+        workspace.join("raw_proxies.json"),
+        serde_json::to_string(&proxies)?,
+    )?;
     info!("Scraped {} proxies", proxies.len());
     Ok(proxies)
 }
 
+fn import_proxy_list(path: &Path, default_proto: &str) -> Result<Vec<Proxy>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read proxy list {}", path.display()))?;
+    let proxies = polish::parse_proxy_lines(&text, default_proto);
+    info!("Imported {} proxies from {}", proxies.len(), path.display());
+    Ok(proxies)
+}
+
+/// Fetch a plaintext proxy list with `curl`, run as a subprocess like the
+/// scraper rather than linking an HTTP client. Only http(s) URLs are accepted.
+fn fetch_proxy_list(url: &str, default_proto: &str) -> Result<Vec<Proxy>> {
+    let scheme = url.split("://").next().unwrap_or("").to_ascii_lowercase();
+    if !url.contains("://") || !matches!(scheme.as_str(), "http" | "https") {
+        anyhow::bail!(
+            "--import-url must be an http:// or https:// URL, got {}",
+            url
+        );
+    }
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--proto", "=http,https", "--proto-redir", "=http,https"])
+        .args(["--max-time", "30"])
+        .arg(url)
+        .output()
+        .context("Failed to execute curl")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Fetching {} failed (curl exit code {:?}): {}",
+            url,
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let proxies = polish::parse_proxy_lines(&text, default_proto);
+    info!("Imported {} proxies from {}", proxies.len(), url);
+    Ok(proxies)
+}
+
+/// Flags that shape `run_polish`.
+struct PolishOptions {
+    max_ports_per_ip: Option<usize>,
+    compress: bool,
+    merge_pool: bool,
+}
+
 fn run_polish(
-    workspace: &PathBuf,
+    workspace: &Path,
     proxies: Vec<Proxy>,
+    opts: &PolishOptions,
 ) -> Result<(Vec<Proxy>, Vec<Proxy>, Vec<Proxy>)> {
+    let proxies = if opts.merge_pool {
+        let existing = load_proxies(&workspace.join("proxies_combined.json"))?;
+        info!(
+            "Merging {} proxies into the existing pool of {}",
+            proxies.len(),
+            existing.len()
+        );
+        polish::merge_pools(existing, proxies)
+    } else {
+        proxies
+    };
     info!("Polishing {} proxies...", proxies.len());
     let unique = polish::deduplicate_proxies(proxies);
     let weights = ScoringWeights::default();
-    let scored = polish::calculate_scores(unique, &weights);
+    let mut scored = polish::calculate_scores(unique, &weights);
+    if let Some(max) = opts.max_ports_per_ip {
+        let flagged = polish::flag_suspicious_hosts(&mut scored, max);
+        if flagged > 0 {
+            warn!(
+                "Penalized {} proxies on hosts exposing more than {} ports",
+                flagged, max
+            );
+        }
+    }
     let (dns, non_dns) = polish::split_proxy_pools(scored.clone());
 
     // Save pools
-    fs::write(
-        workspace.join("proxies_dns.json"),
-        serde_json::to_string_pretty(&dns)?,
-    )?;
-    fs::write(
-        workspace.join("proxies_non_dns.json"),
-        serde_json::to_string_pretty(&non_dns)?,
-    )?;
-    fs::write(
-        workspace.join("proxies_combined.json"),
-        serde_json::to_string_pretty(&scored)?,
-    )?;
+    for (name, pool) in [
+        ("proxies_dns.json", &dns),
+        ("proxies_non_dns.json", &non_dns),
+        ("proxies_combined.json", &scored),
+    ] {
+        let json = serde_json::to_string_pretty(pool)?;
+        storage::write_pool_file(&workspace.join(name), json.as_bytes(), opts.compress)?;
+    }
 
     Ok((dns, non_dns, scored))
 }
 
-fn load_proxies(path: &PathBuf) -> Result<Vec<Proxy>> {
-    if !path.exists() {
+fn load_proxies(path: &Path) -> Result<Vec<Proxy>> {
+    let Some(path) = storage::resolve_pool_path(path) else {
         return Ok(Vec::new());
-    }
-    let content = fs::read_to_string(path)?;
+    };
+    let path = path.as_path();
+    let content = storage::read_pool_file(path)?;
     if content.trim().is_empty() {
         return Ok(Vec::new());
     }
-    Ok(serde_json::from_str(&content)?)
+    let mut proxies = parse_proxy_list(&content)?;
+    let before = proxies.len();
+    proxies.retain(Proxy::is_valid);
+    if proxies.len() < before {
+        warn!(
+            "Dropped {} invalid proxies (bad ip/port) from {}",
+            before - proxies.len(),
+            path.display()
+        );
+    }
+    Ok(proxies)
+}
+
+/// Like `load_proxies`, but stops after the first `limit` valid entries without
+/// parsing the rest of the file. Pool files are sorted by descending score, so
+/// this yields the top `limit` proxies.
+fn load_proxies_limited(path: &Path, limit: usize) -> Result<Vec<Proxy>> {
+    struct TopN<'a> {
+        out: &'a mut Vec<Proxy>,
+        limit: usize,
+    }
+
+    impl<'de> Visitor<'de> for TopN<'_> {
+        type Value = ();
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a JSON array of proxies or a single proxy object")
+        }
+
+        // A lone object is a one-proxy pool, as in `parse_proxy_list`
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+            let p = Proxy::deserialize(MapAccessDeserializer::new(map))?;
+            if p.is_valid() {
+                self.out.push(p);
+            }
+            Ok(())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            while self.out.len() < self.limit {
+                match seq.next_element::<Proxy>()? {
+                    Some(p) if p.is_valid() => self.out.push(p),
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let Some(path) = storage::resolve_pool_path(path).filter(|_| limit > 0) else {
+        return Ok(Vec::new());
+    };
+    let path = path.as_path();
+    let mut de = serde_json::Deserializer::from_reader(storage::open_pool_reader(path)?);
+    let mut proxies = Vec::with_capacity(limit);
+    let result = de.deserialize_any(TopN {
+        out: &mut proxies,
+        limit,
+    });
+    match result {
+        Ok(()) => Ok(proxies),
+        // Stopping early leaves the array unterminated, which serde_json reports
+        // as an error once the visitor returns; every entry we kept parsed fine.
+        Err(_) if proxies.len() == limit => Ok(proxies),
+        // An empty file is an empty pool, as in `load_proxies`
+        Err(e) if e.is_eof() && proxies.is_empty() => Ok(proxies),
+        Err(e) => Err(e).with_context(|| format!("Failed to parse {}", path.display())),
+    }
 }
 
-fn load_pools(workspace: &PathBuf) -> Result<(Vec<Proxy>, Vec<Proxy>, Vec<Proxy>)> {
-    let dns = load_proxies(&workspace.join("proxies_dns.json"))?;
-    let non_dns = load_proxies(&workspace.join("proxies_non_dns.json"))?;
-    let combined = load_proxies(&workspace.join("proxies_combined.json"))?;
+/// Parse a pool file entry by entry. Returns the entry count and the index and
+/// reason of every entry that is not a usable `Proxy`; errors only when the file
+/// is neither a JSON array nor a single proxy object.
+fn check_pool_file(path: &Path) -> Result<(usize, Vec<(usize, String)>)> {
+    let content = storage::read_pool_file(path)?;
+    if content.trim().is_empty() {
+        return Ok((0, Vec::new()));
+    }
+    let entries = match serde_json::from_str(&content).context("not valid JSON")? {
+        serde_json::Value::Array(entries) => entries,
+        obj @ serde_json::Value::Object(_) => vec![obj],
+        _ => anyhow::bail!("not a JSON array of proxies"),
+    };
+    let problems = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| match Proxy::deserialize(entry) {
+            Ok(p) if p.is_valid() => None,
+            Ok(p) => Some((i, format!("invalid address {}:{}", p.ip, p.port))),
+            Err(e) => Some((i, e.to_string())),
+        })
+        .collect();
+    Ok((entries.len(), problems))
+}
+
+/// Print a per-file summary for `--step validate`, failing if any file is
+/// structurally invalid. Invalid entries are reported but, like `load_proxies`,
+/// not fatal.
+fn validate_pool_files(files: &[PathBuf]) -> Result<()> {
+    let mut broken = 0;
+    for path in files {
+        let Some(path) = storage::resolve_pool_path(path) else {
+            println!("{}: missing, skipped", path.display());
+            continue;
+        };
+        let path = path.as_path();
+        match check_pool_file(path) {
+            Ok((total, problems)) => {
+                println!(
+                    "{}: {} entries, {} invalid",
+                    path.display(),
+                    total,
+                    problems.len()
+                );
+                for (i, reason) in problems {
+                    println!("  [{}] {}", i, reason);
+                }
+            }
+            Err(e) => {
+                println!("{}: unreadable: {:#}", path.display(), e);
+                broken += 1;
+            }
+        }
+    }
+    if broken > 0 {
+        anyhow::bail!("{} pool file(s) are structurally invalid", broken);
+    }
+    Ok(())
+}
+
+/// Read the blocklist from `path`, or from blocklist.txt in the workspace when
+/// no path is given. A missing default file means an empty blocklist; a missing
+/// explicit one is an error.
+fn load_blocklist(workspace: &Path, path: Option<&Path>) -> Result<Blocklist> {
+    let default_path = workspace.join("blocklist.txt");
+    let path = match path {
+        Some(path) => path,
+        None if default_path.exists() => &default_path,
+        None => return Ok(Blocklist::default()),
+    };
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read blocklist {}", path.display()))?;
+    let blocklist = Blocklist::parse(&text);
+    info!(
+        "Loaded {} blocklist ranges from {}",
+        blocklist.len(),
+        path.display()
+    );
+    Ok(blocklist)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Drop blocklisted entries from a parsed `--exit-set`, with a warning each.
+/// Fails if every exit is blocked, rather than silently building an unpinned
+/// chain.
+fn unblocked_exits(exit_set: Vec<HopInfo>, blocklist: &Blocklist) -> Result<Vec<HopInfo>> {
+    let requested = exit_set.len();
+    let allowed: Vec<HopInfo> = exit_set
+        .into_iter()
+        .filter(|e| match e.ip.parse() {
+            Ok(ip) if blocklist.contains(ip) => {
+                warn!("Skipping blocklisted exit {}", endpoint_key(&e.ip, e.port));
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    if requested > 0 && allowed.is_empty() {
+        anyhow::bail!("Every --exit-set entry is blocklisted");
+    }
+    Ok(allowed)
+}
+
+/// `run_polish` for `--step full`, which builds its chain from the polished
+/// pools directly instead of through `load_pools`: the blocklist is applied
+/// to the returned pools, while the files on disk keep every proxy.
+fn polish_for_chain(
+    workspace: &Path,
+    proxies: Vec<Proxy>,
+    opts: &PolishOptions,
+    blocklist: &Blocklist,
+) -> Result<(Vec<Proxy>, Vec<Proxy>, Vec<Proxy>)> {
+    let (dns, non_dns, combined) = run_polish(workspace, proxies, opts)?;
+    Ok((
+        blocklist.filter(dns),
+        blocklist.filter(non_dns),
+        blocklist.filter(combined),
+    ))
+}
+
+/// Load the dns, non-dns and combined pools, minus blocklisted proxies. With
+/// `pool_file` the combined pool is read from that one file and split with
+/// `polish::split_proxy_pools`, so the three views cannot drift apart.
+fn load_pools(
+    workspace: &Path,
+    pool_file: Option<&Path>,
+    limit: Option<usize>,
+    blocklist: &Blocklist,
+) -> Result<(Vec<Proxy>, Vec<Proxy>, Vec<Proxy>)> {
+    let load = |path: &Path| -> Result<Vec<Proxy>> {
+        let proxies = match limit {
+            Some(n) => load_proxies_limited(path, n)?,
+            None => load_proxies(path)?,
+        };
+        Ok(blocklist.filter(proxies))
+    };
+    if let Some(path) = pool_file {
+        if storage::resolve_pool_path(path).is_none() {
+            anyhow::bail!("Pool file {} does not exist", path.display());
+        }
+        let combined = load(path)?;
+        polish::warn_clock_skew(&combined, unix_now());
+        let (dns, non_dns) = polish::split_proxy_pools(combined.clone());
+        return Ok((dns, non_dns, combined));
+    }
+    let load = |name: &str| load(&workspace.join(name));
+    let dns = load("proxies_dns.json")?;
+    let non_dns = load("proxies_non_dns.json")?;
+    let combined = load("proxies_combined.json")?;
+    polish::warn_clock_skew(&combined, unix_now());
     Ok((dns, non_dns, combined))
 }
 
-fn print_decision(d: &RotationDecision) {
-    println!("{}", serde_json::to_string_pretty(d).unwrap());
+/// Hop keys of the chain in last_chain.json, so the next rotation can avoid
+/// handing out the same chain twice in a row.
+fn load_previous_hops(workspace: &Path) -> Vec<String> {
+    fs::read_to_string(workspace.join("last_chain.json"))
+        .ok()
+        .and_then(|raw| serde_json::from_str::<ChainTopology>(&raw).ok())
+        .map(|t| t.hops.iter().map(|h| endpoint_key(&h.ip, h.port)).collect())
+        .unwrap_or_default()
+}
+
+/// Persist the chain topology (never keys) to last_chain.json.
+fn save_last_chain(workspace: &Path, d: &RotationDecision) -> Result<()> {
+    fs::write(
+        workspace.join("last_chain.json"),
+        serde_json::to_string_pretty(&d.to_chain_topology())?,
+    )?;
+    Ok(())
+}
+
+/// Load the persisted known-good topology, if any. Unreadable files are ignored.
+fn load_known_good(workspace: &Path) -> Option<ChainTopology> {
+    let raw = fs::read_to_string(workspace.join("known_good_chain.json")).ok()?;
+    match serde_json::from_str::<ChainTopology>(&raw) {
+        Ok(t) => Some(t),
+        Err(e) => {
+            warn!("Ignoring unreadable known_good_chain.json: {}", e);
+            None
+        }
+    }
+}
+
+/// Promote last_chain.json to the known-good fallback. Called once the chain
+/// has successfully served traffic.
+fn mark_known_good(workspace: &Path) -> Result<()> {
+    let raw = fs::read_to_string(workspace.join("last_chain.json"))
+        .context("No last_chain.json to mark as known-good")?;
+    let mut topology: ChainTopology =
+        serde_json::from_str(&raw).context("Failed to parse last_chain.json")?;
+    topology.known_good = true;
+    fs::write(
+        workspace.join("known_good_chain.json"),
+        serde_json::to_string_pretty(&topology)?,
+    )?;
+    info!("Marked chain {} as known-good", topology.chain_id);
+    Ok(())
+}
+
+fn render_decision(d: &RotationDecision, format: &str) -> Result<String> {
+    match format {
+        "json" => Ok(format!("{}\n", serde_json::to_string_pretty(d)?)),
+        "env" => Ok(export::render_env(d)),
+        "proxychains" => Ok(export::render_proxychains(d)),
+        "first-hop" => Ok(export::render_first_hop(d)),
+        other => anyhow::bail!(
+            "Unknown output format: {} (expected json, env or proxychains)",
+            other
+        ),
+    }
+}
+
+fn print_decision(d: &RotationDecision, format: &str) -> Result<()> {
+    print!("{}", render_decision(d, format)?);
+    Ok(())
+}
+
+/// Print the decision, or write it to `output`. With a passphrase the file holds
+/// a `SealedDecision` so the hop keys are never stored in plaintext.
+fn write_decision(
+    d: &RotationDecision,
+    format: &str,
+    output: Option<&Path>,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    let Some(path) = output else {
+        return print_decision(d, format);
+    };
+    let content = match passphrase {
+        Some(pass) => {
+            if format != "json" {
+                anyhow::bail!(
+                    "Encrypted decision files are JSON only (got --format {})",
+                    format
+                );
+            }
+            serde_json::to_string_pretty(&crypto::seal_decision(d, pass)?)?
+        }
+        None => render_decision(d, format)?,
+    };
+    fs::write(path, content)
+        .with_context(|| format!("Failed to write decision to {}", path.display()))?;
+    info!("Wrote chain decision {} to {}", d.chain_id, path.display());
+    Ok(())
+}
+
+/// Load a decision file written by `write_decision`, decrypting it if sealed.
+fn load_decision_file(path: &Path, passphrase: Option<&str>) -> Result<RotationDecision> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read decision file {}", path.display()))?;
+    if let Ok(sealed) = serde_json::from_str::<SealedDecision>(&raw) {
+        let pass = passphrase.context("Decision file is encrypted; pass --decision-passphrase")?;
+        return crypto::open_decision(&sealed, pass);
+    }
+    serde_json::from_str(&raw).context("Failed to parse decision file")
+}
+
+/// Load a decision for `--replay`, refusing files that could not be dialled
+/// as-is.
+fn load_replay(path: &Path, passphrase: Option<&str>) -> Result<RotationDecision> {
+    let d = load_decision_file(path, passphrase)?;
+    d.validate()
+        .map_err(|e| anyhow::anyhow!("Invalid replay decision {}: {}", path.display(), e))?;
+    info!(
+        "Replaying chain {} ({} hops) from {}",
+        d.chain_id,
+        d.chain.len(),
+        path.display()
+    );
+    Ok(d)
+}
+
+/// Per-hop selection rationale. Goes to stderr so the decision on stdout stays
+/// machine-readable.
+fn print_explanation(d: &RotationDecision, dns: &[Proxy], non_dns: &[Proxy], combined: &[Proxy]) {
+    eprintln!("\n=== Chain {} ({} mode) ===", d.chain_id, d.mode);
+    eprintln!("{}", rotator::explain_decision(d, dns, non_dns, combined));
 }
 
-fn print_stats(workspace: &PathBuf) -> Result<()> {
-    let (dns, non_dns, combined) = load_pools(workspace)?;
+fn print_stats(workspace: &Path, pool_file: Option<&Path>, blocklist: &Blocklist) -> Result<()> {
+    let (dns, non_dns, combined) = load_pools(workspace, pool_file, None, blocklist)?;
     println!("\n=== Spectre Network Stats ===");
     println!("Total proxies (Combined): {}", combined.len());
     println!("DNS-Capable: {}", dns.len());
@@ -223,3 +1069,391 @@ fn print_summary(total: usize, dns: usize, non_dns: usize) {
     println!("DNS-capable: {}", dns);
     println!("Non-DNS: {}", non_dns);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_pool_keeps_verified_records() {
+        let workspace = std::env::temp_dir().join(format!("spectre-merge-{}", std::process::id()));
+        fs::create_dir_all(&workspace).unwrap();
+        let verified = serde_json::json!([{
+            "ip": "192.168.1.1", "port": 1080, "type": "socks5",
+            "fail_count": 2, "last_verified": 1_700_000_000u64, "country": "de",
+        }]);
+        fs::write(
+            workspace.join("proxies_combined.json"),
+            verified.to_string(),
+        )
+        .unwrap();
+        let fresh: Vec<Proxy> = serde_json::from_value(serde_json::json!([
+            {"ip": "192.168.1.1", "port": 1080, "type": "socks5"},
+            {"ip": "192.168.2.1", "port": 1080, "type": "socks5"},
+        ]))
+        .unwrap();
+
+        let opts = PolishOptions {
+            max_ports_per_ip: None,
+            compress: false,
+            merge_pool: true,
+        };
+        let (_, _, combined) = run_polish(&workspace, fresh, &opts).unwrap();
+        fs::remove_dir_all(&workspace).unwrap();
+
+        assert_eq!(combined.len(), 2);
+        let kept = combined.iter().find(|p| p.ip == "192.168.1.1").unwrap();
+        assert_eq!(kept.fail_count, 2);
+        assert_eq!(kept.last_verified, 1_700_000_000);
+        assert_eq!(kept.country, "de");
+    }
+
+    #[test]
+    fn test_per_mode_chain_flags_parse() {
+        let cli = Cli::try_parse_from([
+            "spectre",
+            "--mode-floor",
+            "phantom=0.6,High=0.4",
+            "--mode-ports",
+            "phantom=443,1080",
+            "--mode-ports",
+            "stealth=80",
+            "--require-connect",
+            "--min-dns-hops",
+            "2",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.mode_floors,
+            vec![("phantom".to_string(), 0.6), ("high".to_string(), 0.4)]
+        );
+        assert_eq!(
+            cli.mode_ports,
+            vec![
+                ("phantom".to_string(), vec![443, 1080]),
+                ("stealth".to_string(), vec![80]),
+            ]
+        );
+        assert!(cli.require_connect);
+        assert_eq!(cli.min_dns_hops, 2);
+
+        assert!(parse_mode_floor("phantom=1.5").is_err());
+        assert!(parse_mode_floor("turbo=0.5").is_err());
+        assert!(parse_mode_ports("phantom=443,0").is_err());
+        assert!(parse_mode_ports("phantom").is_err());
+    }
+
+    #[test]
+    fn test_decay_half_life_flag() {
+        let cli = Cli::try_parse_from(["spectre"]).unwrap();
+        assert_eq!(cli.decay_half_life_secs, DEFAULT_DECAY_HALF_LIFE_SECS);
+        let cli = Cli::try_parse_from(["spectre", "--decay-half-life-secs", "0"]).unwrap();
+        assert_eq!(cli.decay_half_life_secs, 0);
+    }
+
+    #[test]
+    fn test_replay_uses_exact_hops_from_file() {
+        let pool: Vec<Proxy> = (1..=6)
+            .map(|i| {
+                serde_json::from_value(serde_json::json!({
+                    "ip": format!("192.168.{}.1", i),
+                    "port": 1080,
+                    "type": "socks5",
+                    "score": 0.8,
+                    "last_verified": unix_now(),
+                }))
+                .unwrap()
+            })
+            .collect();
+        let recorded = rotator::build_chain_decision("phantom", &[], &[], &pool).unwrap();
+        let path = std::env::temp_dir().join(format!("spectre-replay-{}.json", recorded.chain_id));
+        fs::write(&path, serde_json::to_string(&recorded).unwrap()).unwrap();
+
+        let replayed = load_replay(&path, None).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayed.chain_id, recorded.chain_id);
+        let hops = |d: &RotationDecision| {
+            d.chain
+                .iter()
+                .map(|h| format!("{}://{}:{}", h.proto, h.ip, h.port))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hops(&replayed), hops(&recorded));
+        let keys = |d: &RotationDecision| {
+            d.encryption
+                .iter()
+                .map(|c| c.key_hex.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&replayed), keys(&recorded));
+    }
+
+    #[test]
+    fn test_replay_rejects_malformed_decision() {
+        let pool: Vec<Proxy> = vec![serde_json::from_value(serde_json::json!({
+            "ip": "192.168.1.1",
+            "port": 1080,
+            "type": "socks5",
+        }))
+        .unwrap()];
+        let mut d = rotator::build_chain_decision("lite", &[], &[], &pool).unwrap();
+        d.encryption[0].key_hex.truncate(10);
+        let path = std::env::temp_dir().join(format!("spectre-replay-bad-{}.json", d.chain_id));
+        fs::write(&path, serde_json::to_string(&d).unwrap()).unwrap();
+
+        let err = load_replay(&path, None).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("malformed key material"));
+    }
+
+    #[test]
+    fn test_load_proxies_limited_stops_after_n() {
+        let entries: Vec<String> = (1..=3)
+            .map(|i| {
+                format!(
+                    r#"{{"ip": "192.168.{}.1", "port": 1080, "type": "socks5"}}"#,
+                    i
+                )
+            })
+            .collect();
+        // Everything after the third entry is garbage; a full parse would fail
+        let raw = format!("[{}, {{\"ip\": not json at all", entries.join(", "));
+        let path =
+            std::env::temp_dir().join(format!("spectre-limited-{}.json", std::process::id()));
+        fs::write(&path, raw).unwrap();
+
+        let top = load_proxies_limited(&path, 3);
+        let full = load_proxies(&path);
+        fs::remove_file(&path).unwrap();
+
+        let top = top.unwrap();
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].ip, "192.168.1.1");
+        assert_eq!(top[2].ip, "192.168.3.1");
+        assert!(full.is_err());
+    }
+
+    #[test]
+    fn test_single_pool_file_split_in_memory() {
+        let raw = r#"[
+            {"ip": "192.168.1.1", "port": 1080, "type": "socks5"},
+            {"ip": "192.168.1.2", "port": 443, "type": "https"},
+            {"ip": "192.168.1.3", "port": 8080, "type": "http"},
+            {"ip": "192.168.1.4", "port": 1080, "type": "socks4"}
+        ]"#;
+        let path = std::env::temp_dir().join(format!("spectre-pool-{}.json", std::process::id()));
+        fs::write(&path, raw).unwrap();
+
+        let pools = load_pools(
+            Path::new("/nonexistent"),
+            Some(&path),
+            None,
+            &Blocklist::default(),
+        );
+        fs::remove_file(&path).unwrap();
+
+        let (dns, non_dns, combined) = pools.unwrap();
+        assert_eq!(combined.len(), 4);
+        assert_eq!(dns.len(), 2);
+        assert_eq!(non_dns.len(), 1);
+        assert_eq!(non_dns[0].ip, "192.168.1.3");
+    }
+
+    #[test]
+    fn test_check_pool_file_reports_invalid_entries() {
+        let dir = std::env::temp_dir();
+        let good = dir.join(format!("spectre-validate-{}.json", std::process::id()));
+        fs::write(
+            &good,
+            r#"[
+                {"ip": "192.168.1.1", "port": 1080, "type": "socks5"},
+                {"ip": "192.168.1.2", "port": 0, "type": "socks5"},
+                {"ip": "192.168.1.3", "type": "socks5"}
+            ]"#,
+        )
+        .unwrap();
+        let broken = dir.join(format!(
+            "spectre-validate-broken-{}.json",
+            std::process::id()
+        ));
+        fs::write(&broken, r#"{"ip": "192.168.1.1""#).unwrap();
+
+        let report = check_pool_file(&good);
+        let summary = validate_pool_files(&[good.clone(), broken.clone()]);
+        fs::remove_file(&good).unwrap();
+        fs::remove_file(&broken).unwrap();
+
+        let (total, problems) = report.unwrap();
+        assert_eq!(total, 3);
+        let indices: Vec<usize> = problems.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert!(problems[1].1.contains("port"));
+        assert!(summary.is_err());
+    }
+
+    #[test]
+    fn test_pool_loaders_accept_single_object() {
+        let path = std::env::temp_dir().join(format!("spectre-single-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"ip": "192.168.1.1", "port": 1080, "type": "socks5"}"#,
+        )
+        .unwrap();
+
+        let full = load_proxies(&path);
+        let limited = load_proxies_limited(&path, 5);
+        let checked = check_pool_file(&path);
+        fs::write(&path, "").unwrap();
+        let empty = load_proxies_limited(&path, 5);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(full.unwrap().len(), 1);
+        assert_eq!(limited.unwrap()[0].ip, "192.168.1.1");
+        assert_eq!(checked.unwrap(), (1, Vec::new()));
+        assert!(empty.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_network_steps() {
+        assert!(step_needs_network("scrape"));
+        assert!(step_needs_network("full"));
+        for step in [
+            "polish",
+            "rotate",
+            "open",
+            "mark-good",
+            "validate",
+            "export",
+        ] {
+            assert!(!step_needs_network(step), "{} should run offline", step);
+        }
+    }
+
+    #[test]
+    fn test_scrape_backoff_doubles_and_caps() {
+        let retry = ScrapeRetry {
+            retries: 3,
+            base_delay: Duration::from_millis(500),
+        };
+        assert_eq!(retry.backoff(0), Duration::from_millis(500));
+        assert_eq!(retry.backoff(2), Duration::from_secs(2));
+        assert_eq!(retry.backoff(40), MAX_SCRAPE_BACKOFF);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_scraper_retries_failed_runs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("spectre_scrape_retry_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Fails twice, then prints one proxy
+        let script = dir.join("go_scraper");
+        fs::write(
+            &script,
+            "#!/bin/sh\n\
+             n=$(cat \"$(dirname \"$0\")/runs\" 2>/dev/null || echo 0)\n\
+             echo $((n + 1)) > \"$(dirname \"$0\")/runs\"\n\
+             [ \"$n\" -ge 2 ] || exit 1\n\
+             echo '[{\"ip\": \"192.168.1.1\", \"port\": 8080, \"type\": \"http\"}]'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let retry = |retries| ScrapeRetry {
+            retries,
+            base_delay: Duration::from_millis(1),
+        };
+        let single = run_scraper(&dir, 10, "all", &retry(0));
+        let retried = run_scraper(&dir, 10, "all", &retry(2));
+        let runs = fs::read_to_string(dir.join("runs")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(single.is_err());
+        assert_eq!(retried.unwrap().len(), 1);
+        assert_eq!(runs.trim(), "3");
+    }
+
+    #[test]
+    fn test_import_url_fetches_and_parses_list() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = conn.read(&mut request);
+            let body = "192.168.1.1:8080\nnot a proxy\nsocks5://192.168.1.2:1080\n";
+            write!(
+                conn,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let proxies = fetch_proxy_list(&format!("http://{}/list.txt", addr), "http").unwrap();
+        server.join().unwrap();
+        assert_eq!(proxies.len(), 2);
+        assert_eq!(proxies[0].proto, "http");
+        assert_eq!(proxies[1].proto, "socks5");
+
+        // Anything curl could read locally is refused up front
+        assert!(fetch_proxy_list("file:///etc/passwd", "http").is_err());
+        assert!(fetch_proxy_list("192.168.1.1/list.txt", "http").is_err());
+    }
+
+    #[test]
+    fn test_full_step_pools_drop_blocklisted() {
+        let workspace =
+            std::env::temp_dir().join(format!("spectre-full-block-{}", std::process::id()));
+        fs::create_dir_all(&workspace).unwrap();
+        let raw: Vec<Proxy> = ["10.0.0.1", "10.0.0.2", "192.168.1.1"]
+            .iter()
+            .map(|ip| {
+                serde_json::from_value(serde_json::json!({
+                    "ip": ip,
+                    "port": 1080,
+                    "type": "socks5",
+                    "latency": 0.5,
+                }))
+                .unwrap()
+            })
+            .collect();
+        let opts = PolishOptions {
+            max_ports_per_ip: None,
+            compress: false,
+            merge_pool: false,
+        };
+        let blocklist = Blocklist::parse("10.0.0.0/24\n");
+
+        let (dns, non_dns, combined) =
+            polish_for_chain(&workspace, raw, &opts, &blocklist).unwrap();
+        let on_disk = load_proxies(&workspace.join("proxies_combined.json")).unwrap();
+        fs::remove_dir_all(&workspace).unwrap();
+
+        for pool in [&dns, &non_dns, &combined] {
+            assert!(pool.iter().all(|p| !p.ip.starts_with("10.0.0.")));
+        }
+        assert_eq!(combined.len(), 1);
+        assert_eq!(dns.len(), 1);
+        // The pool files are left whole; the blocklist applies on load
+        assert_eq!(on_disk.len(), 3);
+    }
+
+    #[test]
+    fn test_blocklisted_exit_set_entries_are_skipped() {
+        let blocklist = Blocklist::parse("10.0.0.0/24\n");
+        let exits = rotator::parse_exit_set("10.0.0.5:1080,192.168.1.9:1080").unwrap();
+        let allowed = unblocked_exits(exits, &blocklist).unwrap();
+        assert_eq!(allowed.len(), 1);
+        assert_eq!(allowed[0].ip, "192.168.1.9");
+
+        let all_blocked = rotator::parse_exit_set("10.0.0.5:1080").unwrap();
+        assert!(unblocked_exits(all_blocked, &blocklist).is_err());
+        assert!(unblocked_exits(Vec::new(), &blocklist).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+//! # Spectre Metrics - Prometheus Exposition
+//!
+//! This module renders pool and rotation statistics in the Prometheus text
+//! exposition format (`# HELP` / `# TYPE` / samples). It performs no I/O; the
+//! host that owns the listener (the Go orchestrator, or a Python service) serves
+//! the rendered text on its `/metrics` endpoint.
+
+use crate::rotator::{self, ChainBuildCount, ChainBuildFailures};
+use crate::types::{Proxy, ProxyTier};
+use std::fmt::Write;
+
+/// Tiers in the order their gauges are emitted.
+const TIERS: [ProxyTier; 5] = [
+    ProxyTier::Platinum,
+    ProxyTier::Gold,
+    ProxyTier::Silver,
+    ProxyTier::Bronze,
+    ProxyTier::Dead,
+];
+
+/// Point-in-time pool statistics to export.
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats {
+    pub proxies_total: usize,
+    pub proxies_alive: usize,
+    /// Hops in the currently active chain (0 when none)
+    pub chain_hops: usize,
+    /// Wall time of the last verification pass, measured by the verifier
+    pub verify_duration_seconds: f64,
+    /// Proxy count per tier, indexed like `TIERS`
+    pub tier_counts: [usize; 5],
+    pub chain_build_failures: ChainBuildFailures,
+    /// Chain builds by mode and outcome
+    pub chain_builds: Vec<ChainBuildCount>,
+}
+
+impl PoolStats {
+    /// Collect counts from `pool` and the process-wide chain build
+    /// counters. `chain_hops` and `verify_duration_seconds` are left for the
+    /// caller, which knows the active chain and the verifier timings.
+    pub fn from_pool(pool: &[Proxy]) -> Self {
+        let mut tier_counts = [0usize; 5];
+        for p in pool {
+            if let Some(i) = TIERS.iter().position(|t| *t == p.tier) {
+                tier_counts[i] += 1;
+            }
+        }
+        PoolStats {
+            proxies_total: pool.len(),
+            proxies_alive: pool.iter().filter(|p| p.alive).count(),
+            tier_counts,
+            chain_build_failures: rotator::chain_build_failures(),
+            chain_builds: rotator::chain_build_counts(),
+            ..PoolStats::default()
+        }
+    }
+}
+
+fn tier_label(tier: ProxyTier) -> &'static str {
+    match tier {
+        ProxyTier::Platinum => "platinum",
+        ProxyTier::Gold => "gold",
+        ProxyTier::Silver => "silver",
+        ProxyTier::Bronze => "bronze",
+        ProxyTier::Dead => "dead",
+    }
+}
+
+/// Escape a label value per the exposition format (mode names are free-form).
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Render `stats` in the Prometheus text exposition format.
+pub fn render_prometheus(stats: &PoolStats) -> String {
+    let mut out = String::new();
+
+    write_header(
+        &mut out,
+        "spectre_proxies_total",
+        "gauge",
+        "Proxies in the combined pool.",
+    );
+    let _ = writeln!(out, "spectre_proxies_total {}", stats.proxies_total);
+
+    write_header(
+        &mut out,
+        "spectre_proxies_alive",
+        "gauge",
+        "Proxies whose last verification probe succeeded.",
+    );
+    let _ = writeln!(out, "spectre_proxies_alive {}", stats.proxies_alive);
+
+    write_header(
+        &mut out,
+        "spectre_proxies_tier",
+        "gauge",
+        "Proxies per quality tier.",
+    );
+    for (tier, count) in TIERS.iter().zip(stats.tier_counts) {
+        let _ = writeln!(
+            out,
+            "spectre_proxies_tier{{tier=\"{}\"}} {}",
+            tier_label(*tier),
+            count
+        );
+    }
+
+    write_header(
+        &mut out,
+        "spectre_chain_hops",
+        "gauge",
+        "Hops in the active chain.",
+    );
+    let _ = writeln!(out, "spectre_chain_hops {}", stats.chain_hops);
+
+    write_header(
+        &mut out,
+        "spectre_verify_duration_seconds",
+        "gauge",
+        "Duration of the last pool verification pass.",
+    );
+    let _ = writeln!(
+        out,
+        "spectre_verify_duration_seconds {}",
+        stats.verify_duration_seconds
+    );
+
+    write_header(
+        &mut out,
+        "spectre_chain_build_failures_total",
+        "counter",
+        "Chain builds that failed, by reason.",
+    );
+    let failures = &stats.chain_build_failures;
+    let _ = writeln!(
+        out,
+        "spectre_chain_build_failures_total{{reason=\"empty_pool\"}} {}",
+        failures.empty_pool
+    );
+    let _ = writeln!(
+        out,
+        "spectre_chain_build_failures_total{{reason=\"unsatisfiable\"}} {}",
+        failures.unsatisfiable
+    );
+
+    write_header(
+        &mut out,
+        "spectre_chain_builds_total",
+        "counter",
+        "Chain build attempts, by mode and outcome.",
+    );
+    for build in &stats.chain_builds {
+        let _ = writeln!(
+            out,
+            "spectre_chain_builds_total{{mode=\"{}\",outcome=\"{}\"}} {}",
+            escape_label(&build.mode),
+            build.outcome.as_str(),
+            build.count
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_proxy(ip: &str, score: f64, alive: bool) -> Proxy {
+        Proxy {
+            ip: ip.to_string(),
+            port: 1080,
+            proto: "socks5".to_string(),
+            latency: 0.2,
+            jitter: 0.0,
+            country: "us".to_string(),
+            anonymity: "elite".to_string(),
+            score,
+            tier: ProxyTier::from_score(score),
+            fail_count: 0,
+            last_verified: 0,
+            alive,
+            source_type: "standard".to_string(),
+            probes_total: 0,
+            probes_ok: 0,
+            supports_connect: true,
+        }
+    }
+
+    /// Minimal exposition-format check: every sample belongs to a family
+    /// declared by a preceding `# HELP` and `# TYPE`, metric names are valid,
+    /// and every value parses as a float.
+    fn assert_valid_exposition(text: &str) {
+        let valid_name = |n: &str| {
+            !n.is_empty()
+                && !n.starts_with(|c: char| c.is_ascii_digit())
+                && n.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        };
+        let mut helped = std::collections::HashSet::new();
+        let mut typed = std::collections::HashSet::new();
+
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                let name = rest.split_whitespace().next().unwrap();
+                assert!(valid_name(name), "bad metric name in: {}", line);
+                helped.insert(name.to_string());
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let mut parts = rest.split_whitespace();
+                let name = parts.next().unwrap();
+                let kind = parts.next().unwrap();
+                assert!(["counter", "gauge", "histogram", "summary", "untyped"].contains(&kind));
+                assert!(helped.contains(name), "TYPE before HELP: {}", line);
+                typed.insert(name.to_string());
+            } else {
+                let (series, value) = line.rsplit_once(' ').expect("sample needs a value");
+                let name = series.split('{').next().unwrap();
+                assert!(valid_name(name), "bad metric name in: {}", line);
+                assert!(typed.contains(name), "sample without TYPE: {}", line);
+                if let Some(labels) = series.strip_prefix(name) {
+                    if !labels.is_empty() {
+                        assert!(labels.starts_with('{') && labels.ends_with('}'));
+                        for pair in labels[1..labels.len() - 1].split(',') {
+                            let (k, v) = pair.split_once('=').expect("label needs k=v");
+                            assert!(valid_name(k));
+                            assert!(v.starts_with('"') && v.ends_with('"'));
+                        }
+                    }
+                }
+                value.parse::<f64>().expect("sample value must be numeric");
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_prometheus_exposition_format() {
+        let pool = vec![
+            make_proxy("192.168.1.1", 0.9, true),
+            make_proxy("192.168.1.2", 0.75, true),
+            make_proxy("192.168.1.3", 0.1, false),
+        ];
+        let mut stats = PoolStats::from_pool(&pool);
+        stats.chain_hops = 3;
+        stats.verify_duration_seconds = 12.5;
+        stats.chain_builds = vec![
+            ChainBuildCount {
+                mode: "phantom".to_string(),
+                outcome: rotator::BuildOutcome::Empty,
+                count: 4,
+            },
+            ChainBuildCount {
+                mode: "high".to_string(),
+                outcome: rotator::BuildOutcome::Success,
+                count: 7,
+            },
+        ];
+
+        let text = render_prometheus(&stats);
+        assert_valid_exposition(&text);
+
+        assert!(text.contains("spectre_proxies_total 3\n"));
+        assert!(text.contains("spectre_proxies_alive 2\n"));
+        assert!(text.contains("spectre_chain_hops 3\n"));
+        assert!(text.contains("spectre_verify_duration_seconds 12.5\n"));
+        assert!(text.contains("spectre_proxies_tier{tier=\"platinum\"} 1\n"));
+        assert!(text.contains("spectre_proxies_tier{tier=\"gold\"} 1\n"));
+        assert!(text.contains("spectre_proxies_tier{tier=\"dead\"} 1\n"));
+        assert!(text.contains("spectre_chain_builds_total{mode=\"phantom\",outcome=\"empty\"} 4\n"));
+        assert!(text.contains("spectre_chain_builds_total{mode=\"high\",outcome=\"success\"} 7\n"));
+    }
+}
@@ -4,10 +4,203 @@
 //! operational modes. It implements weighted random selection, subnet diversity constraints,
 //! and deterministic key derivation from master secrets.
 
-use crate::types::{ChainHop, ChainTopology, CryptoHop, Proxy, ProxyTier, RotationDecision};
+use crate::types::{
+    endpoint_key, ChainHop, ChainOptions, ChainTopology, CryptoHop, HopInfo, HopSelection, Proxy,
+    ProxyTier, RotationDecision, SelectionTrace,
+};
 use rand::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Chain build failures since process start, by reason. Long-lived hosts (the Go
+/// orchestrator via FFI, Python) read these to alert when rotations stop working.
+static FAILURES_EMPTY_POOL: AtomicU64 = AtomicU64::new(0);
+static FAILURES_UNSATISFIABLE: AtomicU64 = AtomicU64::new(0);
+
+/// Snapshot of the `chain_build_failures` counter with its per-reason breakdown.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ChainBuildFailures {
+    pub total: u64,
+    /// The mode filter left no eligible proxies
+    pub empty_pool: u64,
+    /// Proxies were eligible but too few to meet the mode's minimum hop count
+    pub unsatisfiable: u64,
+}
+
+pub fn chain_build_failures() -> ChainBuildFailures {
+    let empty_pool = FAILURES_EMPTY_POOL.load(Ordering::Relaxed);
+    let unsatisfiable = FAILURES_UNSATISFIABLE.load(Ordering::Relaxed);
+    ChainBuildFailures {
+        total: empty_pool + unsatisfiable,
+        empty_pool,
+        unsatisfiable,
+    }
+}
+
+/// Result class of a chain build, for the per-mode build counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildOutcome {
+    Success,
+    /// The mode (and country) filters left no eligible proxies
+    Empty,
+    /// Proxies were eligible but could not satisfy the mode's constraints
+    Insufficient,
+}
+
+impl BuildOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BuildOutcome::Success => "success",
+            BuildOutcome::Empty => "empty",
+            BuildOutcome::Insufficient => "insufficient",
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Chain builds since process start, by mode and outcome. Modes are keyed
+    /// by name since `ChainOptions::mode_protocols` can define custom ones.
+    static ref BUILD_OUTCOMES: Mutex<BTreeMap<(String, BuildOutcome), u64>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// One `(mode, outcome)` series of the chain build counter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainBuildCount {
+    pub mode: String,
+    pub outcome: BuildOutcome,
+    pub count: u64,
+}
+
+/// Snapshot of the per-mode chain build counter, sorted by mode then outcome.
+/// Every attempt of `build_chain_decision_degrading` is counted under the
+/// mode it tried, so a degraded rotation shows up as a failure of the
+/// requested mode plus a success of the weaker one.
+pub fn chain_build_counts() -> Vec<ChainBuildCount> {
+    let counts = BUILD_OUTCOMES.lock().unwrap_or_else(|e| e.into_inner());
+    counts
+        .iter()
+        .map(|((mode, outcome), count)| ChainBuildCount {
+            mode: mode.clone(),
+            outcome: *outcome,
+            count: *count,
+        })
+        .collect()
+}
+
+/// Bump the failure counters and the per-mode outcome counter for a build.
+fn record_build(mode: &str, decision: &Result<RotationDecision, ChainError>) {
+    let outcome = match decision {
+        Ok(_) => BuildOutcome::Success,
+        Err(ChainError::EmptyPool { .. } | ChainError::CountryFiltered { .. }) => {
+            FAILURES_EMPTY_POOL.fetch_add(1, Ordering::Relaxed);
+            BuildOutcome::Empty
+        }
+        Err(
+            ChainError::InsufficientProxies { .. }
+            | ChainError::InsufficientDnsHops { .. }
+            | ChainError::NoSelection { .. }
+            | ChainError::ExitBelowFloor { .. },
+        ) => {
+            FAILURES_UNSATISFIABLE.fetch_add(1, Ordering::Relaxed);
+            BuildOutcome::Insufficient
+        }
+    };
+    let mut counts = BUILD_OUTCOMES.lock().unwrap_or_else(|e| e.into_inner());
+    *counts.entry((mode.to_string(), outcome)).or_insert(0) += 1;
+}
+
+/// Why a chain could not be built for a mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// The mode filter left no eligible proxies
+    EmptyPool { mode: String },
+    /// Fewer eligible proxies than the mode's minimum hop count
+    InsufficientProxies {
+        mode: String,
+        required: usize,
+        available: usize,
+    },
+    /// Fewer DNS-capable proxies than `ChainOptions::min_dns_hops` demands
+    InsufficientDnsHops {
+        mode: String,
+        required: usize,
+        available: usize,
+    },
+    /// The `ChainStrategy` returned no usable hop set
+    NoSelection { mode: String },
+    /// The country filters left no eligible proxies, or rejected the pinned exit
+    CountryFiltered {
+        mode: String,
+        include: Vec<String>,
+        exclude: Vec<String>,
+    },
+    /// The pinned exit scores below the mode's floor (`mode_score_floor`)
+    ExitBelowFloor { mode: String, exit: String },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::EmptyPool { mode } => {
+                write!(f, "no eligible proxies for mode '{}'", mode)
+            }
+            ChainError::InsufficientProxies {
+                mode,
+                required,
+                available,
+            } => write!(
+                f,
+                "mode '{}' needs at least {} hops but only {} proxies are eligible",
+                mode, required, available
+            ),
+            ChainError::InsufficientDnsHops {
+                mode,
+                required,
+                available,
+            } => write!(
+                f,
+                "mode '{}' needs {} DNS-capable hops but only {} are eligible",
+                mode, required, available
+            ),
+            ChainError::NoSelection { mode } => {
+                write!(
+                    f,
+                    "chain strategy selected no usable hops for mode '{}'",
+                    mode
+                )
+            }
+            ChainError::CountryFiltered {
+                mode,
+                include,
+                exclude,
+            } => write!(
+                f,
+                "no eligible proxies for mode '{}' with country filters include=[{}] exclude=[{}]",
+                mode,
+                include.join(","),
+                exclude.join(",")
+            ),
+            ChainError::ExitBelowFloor { mode, exit } => write!(
+                f,
+                "pinned exit {} is below the score floor of mode '{}'",
+                exit, mode
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+/// Maximum reshuffles when a selection repeats the previous chain's hop set.
+const MAX_RESHUFFLE_ATTEMPTS: usize = 8;
+
 fn now_unix() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -15,14 +208,21 @@ fn now_unix() -> u64 {
         .as_secs()
 }
 
+/// Proxy protocols chains can be built from, in their normalized spelling.
+pub const PROXY_PROTOCOLS: [&str; 4] = ["http", "https", "socks4", "socks5"];
+
+/// SOCKS commands a built chain is expected to carry. Every hop is used as a
+/// CONNECT tunnel; `ChainOptions::require_connect` checks the exit for it.
+pub const SOCKS_COMMANDS: [&str; 1] = ["connect"];
+
 fn normalize_proto(p: &str) -> String {
-    match p.to_lowercase().as_str() {
-        "http" => "http".into(),
-        "https" => "https".into(),
-        "socks" => "socks5".into(),
-        "socks5" => "socks5".into(),
-        "socks4" => "socks4".into(),
-        other => other.to_string(),
+    let lower = p.to_lowercase();
+    if lower == "socks" {
+        return "socks5".into();
+    }
+    match PROXY_PROTOCOLS.iter().find(|known| **known == lower) {
+        Some(known) => known.to_string(),
+        None => lower,
     }
 }
 
@@ -140,11 +340,85 @@ pub fn reconstruct_decision_from_topology(
         chain_id: topology.chain_id.clone(),
         chain,
         avg_latency: topology.avg_latency,
+        total_latency: topology.avg_latency * topology.hops.len() as f64,
         min_score: topology.min_score,
         max_score: topology.max_score,
         encryption,
         garlic: false,
+        selection: None,
+    }
+}
+
+/// Whether `p` is the endpoint `ip:port`, compared by canonical key so that
+/// e.g. `2001:DB8::1` and `2001:db8::1` match.
+fn is_endpoint(p: &Proxy, ip: &str, port: u16) -> bool {
+    p.port == port && p.key() == endpoint_key(ip, port)
+}
+
+/// Rebuild a decision from a persisted known-good topology.
+///
+/// The topology is re-validated against the current pool: it must carry the
+/// known-good marker and every hop must still be present, valid, alive,
+/// allowed by the country filters and at or above the score floor of the
+/// topology's mode. Hop metadata (country, latency, score) is
+/// refreshed from the pool records and fresh key material is generated per
+/// `opts`.
+pub fn decision_from_known_good(
+    topology: &ChainTopology,
+    pool: &[Proxy],
+    opts: &ChainOptions,
+) -> Option<RotationDecision> {
+    if !topology.known_good || topology.hops.is_empty() {
+        return None;
     }
+
+    let floor = mode_score_floor(&topology.mode, opts);
+    let now = now_unix();
+    let mut rng = StdRng::from_entropy();
+    let mut chain = Vec::with_capacity(topology.hops.len());
+    let mut crypto = Vec::new();
+    for h in &topology.hops {
+        let p = pool
+            .iter()
+            .find(|p| is_endpoint(p, &h.ip, h.port) && p.alive && p.is_valid())
+            .filter(|p| opts.country_allowed(&p.country))
+            .filter(|p| p.effective_score_at(&opts.scoring, now) >= floor)?;
+        chain.push(ChainHop {
+            ip: p.ip.clone(),
+            port: p.port,
+            proto: normalize_proto(&h.proto),
+            country: p.country.clone(),
+            latency: if p.latency > 0.0 { p.latency } else { 1.0 },
+            score: if p.score > 0.0 { p.score } else { 0.5 },
+            obfuscation: None,
+        });
+        if opts.with_encryption {
+            let (key_hex, nonce_hex) = generate_key_nonce(&mut rng);
+            crypto.push(CryptoHop { key_hex, nonce_hex });
+        }
+    }
+
+    let total_latency = chain.iter().map(|h| h.latency).sum::<f64>();
+    let avg_latency = total_latency / chain.len() as f64;
+    let min_score = chain.iter().map(|h| h.score).fold(f64::INFINITY, f64::min);
+    let max_score = chain
+        .iter()
+        .map(|h| h.score)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    Some(RotationDecision {
+        mode: topology.mode.clone(),
+        timestamp: now,
+        chain_id: topology.chain_id.clone(),
+        chain,
+        avg_latency,
+        total_latency,
+        min_score,
+        max_score,
+        encryption: crypto,
+        garlic: false,
+        selection: None,
+    })
 }
 
 pub fn filter_mode_pool(
@@ -153,6 +427,114 @@ pub fn filter_mode_pool(
     non_dns: &[Proxy],
     combined: &[Proxy],
 ) -> Vec<Proxy> {
+    filter_mode_pool_with_options(mode, dns, non_dns, combined, &ChainOptions::default())
+}
+
+/// Protocols accepted by `mode`, honouring any override in `opts.mode_protocols`.
+/// An empty list means the mode places no protocol restriction of its own.
+fn mode_protocols(mode: &str, opts: &ChainOptions) -> Vec<String> {
+    if let Some(protos) = opts.mode_protocols.get(mode) {
+        return protos.iter().map(|p| normalize_proto(p)).collect();
+    }
+    match mode {
+        "stealth" => vec!["http".into(), "https".into()],
+        "high" | "phantom" => vec!["https".into(), "socks5".into()],
+        _ => Vec::new(),
+    }
+}
+
+/// Minimum score every hop of a `mode` chain must have, honouring any override
+/// in `opts.mode_score_floors`. The mode filters below prefer better proxies
+/// but fall back to weaker tiers; the floor is the line no fallback crosses.
+/// It is checked against `Proxy::effective_score`, like the tier filters, and
+/// applies to pinned exits and known-good fallback chains as well.
+pub fn mode_score_floor(mode: &str, opts: &ChainOptions) -> f64 {
+    if let Some(floor) = opts.mode_score_floors.get(mode) {
+        return *floor;
+    }
+    match mode {
+        "phantom" => 0.5,
+        // high, stealth and lite rely on their protocol and tier filters
+        _ => 0.0,
+    }
+}
+
+pub fn filter_mode_pool_with_options(
+    mode: &str,
+    dns: &[Proxy],
+    non_dns: &[Proxy],
+    combined: &[Proxy],
+    opts: &ChainOptions,
+) -> Vec<Proxy> {
+    mode_pool(mode, dns, non_dns, combined, opts, now_unix()).0
+}
+
+/// `filter_mode_pool_with_options`, plus the rules that shaped the pool (which
+/// source or fallback filled it, filters and floor) for the selection trace.
+fn mode_pool(
+    mode: &str,
+    dns: &[Proxy],
+    non_dns: &[Proxy],
+    combined: &[Proxy],
+    opts: &ChainOptions,
+    now: u64,
+) -> (Vec<Proxy>, Vec<String>) {
+    let mut rules = Vec::new();
+    // Country and port filters apply before the mode's tier fallbacks, so a
+    // fallback still kicks in when the filters empty the preferred tier.
+    let ports = opts.mode_ports.get(mode);
+    let keep_eligible = |ps: &[Proxy]| -> Vec<Proxy> {
+        ps.iter()
+            .filter(|p| opts.country_allowed(&p.country))
+            .filter(|p| ports.is_none_or(|ports| ports.contains(&p.port)))
+            .cloned()
+            .collect()
+    };
+    if opts.has_country_filter() {
+        rules.push(format!(
+            "countries: include [{}], exclude [{}]",
+            opts.include_countries.join(", "),
+            opts.exclude_countries.join(", ")
+        ));
+    }
+    if let Some(ports) = ports {
+        let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+        rules.push(format!("ports: {}", ports.join(", ")));
+    }
+    let filtered;
+    let (dns, non_dns, combined) = if opts.has_country_filter() || ports.is_some() {
+        filtered = (
+            keep_eligible(dns),
+            keep_eligible(non_dns),
+            keep_eligible(combined),
+        );
+        (&filtered.0[..], &filtered.1[..], &filtered.2[..])
+    } else {
+        (dns, non_dns, combined)
+    };
+
+    let protos = mode_protocols(mode, opts);
+    let allowed = |p: &Proxy| protos.contains(&normalize_proto(&p.proto));
+    let floor = mode_score_floor(mode, opts);
+    rules.push(match (protos.is_empty(), mode) {
+        (false, _) => format!("protocols: {}", protos.join("/")),
+        (true, "lite") => "protocols: any".to_string(),
+        (true, "stealth" | "high" | "phantom") => "protocols: none".to_string(),
+        (true, _) => "protocols: any but SOCKS4".to_string(),
+    });
+
+    // split_proxy_pools never files SOCKS4 under dns/non_dns, so a mode that
+    // opts into SOCKS4 has to pick those proxies up from the combined pool.
+    let socks4: Vec<Proxy> = if protos.iter().any(|p| p == "socks4") {
+        combined
+            .iter()
+            .filter(|p| normalize_proto(&p.proto) == "socks4")
+            .cloned()
+            .collect()
+    } else {
+        Vec::new()
+    };
+
     let mut pool = Vec::new();
     match mode {
         "lite" => {
@@ -162,95 +544,110 @@ pub fn filter_mode_pool(
             if pool.is_empty() {
                 pool.extend_from_slice(dns);
                 pool.extend_from_slice(non_dns);
+                rules.push("source: DNS and non-DNS pools (combined pool empty)".to_string());
+            } else {
+                rules.push("source: combined pool".to_string());
+            }
+            if !protos.is_empty() {
+                pool.retain(|p| allowed(p));
             }
         }
         "stealth" => {
             // Stealth: HTTP/HTTPS only from all pools
             for p in combined.iter().chain(dns).chain(non_dns) {
-                let proto = normalize_proto(&p.proto);
-                if proto == "http" || proto == "https" {
+                if allowed(p) {
                     pool.push(p.clone());
                 }
             }
+            rules.push("source: all pools".to_string());
         }
         "high" => {
             // High: prefers DNS-capable SOCKS5/HTTPS
-            for p in dns {
-                let proto = normalize_proto(&p.proto);
-                if proto == "https" || proto == "socks5" {
+            let mut source = "source: DNS-capable pool";
+            for p in dns.iter().chain(&socks4) {
+                if allowed(p) {
                     pool.push(p.clone());
                 }
             }
             // Fallback to combined if DNS pool is empty
             if pool.is_empty() {
+                source = "fallback: combined pool (no eligible DNS-capable proxy)";
                 for p in combined {
-                    let proto = normalize_proto(&p.proto);
-                    if proto == "https" || proto == "socks5" {
+                    if allowed(p) {
                         pool.push(p.clone());
                     }
                 }
             }
             // Final fallback: use all pools
             if pool.is_empty() {
+                source = "fallback: all pools";
                 for p in combined.iter().chain(dns).chain(non_dns) {
-                    let proto = normalize_proto(&p.proto);
-                    if proto == "https" || proto == "socks5" {
+                    if allowed(p) {
                         pool.push(p.clone());
                     }
                 }
             }
+            rules.push(source.to_string());
         }
         "phantom" => {
             // Phantom: DNS-capable SOCKS5/HTTPS with strict score filtering
             // Primary filter: score >= 0.7 (Gold+ tier) - this is the strict requirement
-            for p in dns {
-                let proto = normalize_proto(&p.proto);
-                if (proto == "socks5" || proto == "https") && p.score >= 0.7 {
+            let mut source = "source: DNS-capable Gold+ (effective score >= 0.70)";
+            for p in dns.iter().chain(&socks4) {
+                if allowed(p) && p.effective_score_at(&opts.scoring, now) >= 0.7 {
                     pool.push(p.clone());
                 }
             }
             // Fallback 1: only if NO Gold+ proxies found, try Silver tier (0.5-0.7)
             if pool.is_empty() {
-                for p in dns {
-                    let proto = normalize_proto(&p.proto);
-                    if (proto == "socks5" || proto == "https") && p.score >= 0.5 {
+                source = "fallback: DNS-capable Silver (effective score >= 0.50)";
+                for p in dns.iter().chain(&socks4) {
+                    if allowed(p) && p.effective_score_at(&opts.scoring, now) >= 0.5 {
                         pool.push(p.clone());
                     }
                 }
             }
             // Fallback 2: if still empty, try combined pool
             if pool.is_empty() {
+                source = "fallback: combined pool (effective score >= 0.50)";
                 for p in combined {
-                    let proto = normalize_proto(&p.proto);
-                    if (proto == "socks5" || proto == "https") && p.score >= 0.5 {
+                    if allowed(p) && p.effective_score_at(&opts.scoring, now) >= 0.5 {
                         pool.push(p.clone());
                     }
                 }
             }
-            // Last resort: if still empty, use any DNS-capable with score >= 0.3
-            if pool.is_empty() {
+            // Last resort: any DNS-capable with score >= 0.3. Skipped unless the
+            // phantom floor has been lowered below Silver, since the floor
+            // would discard everything it adds.
+            if pool.is_empty() && floor < 0.5 {
+                source = "last resort: any pool (effective score >= 0.30)";
                 for p in dns.iter().chain(combined) {
-                    let proto = normalize_proto(&p.proto);
-                    if (proto == "socks5" || proto == "https") && p.score >= 0.3 {
+                    if allowed(p) && p.effective_score_at(&opts.scoring, now) >= 0.3 {
                         pool.push(p.clone());
                     }
                 }
             }
+            rules.push(source.to_string());
         }
         _ => {
             // Default: filter SOCKS4, use Silver+
             for p in combined.iter().chain(dns).chain(non_dns) {
-                let proto = normalize_proto(&p.proto);
-                if proto != "socks4" && p.tier >= ProxyTier::Silver {
+                let proto_ok = if protos.is_empty() {
+                    normalize_proto(&p.proto) != "socks4"
+                } else {
+                    allowed(p)
+                };
+                if proto_ok && p.tier >= ProxyTier::Silver {
                     pool.push(p.clone());
                 }
             }
+            rules.push("source: all pools, Silver+ tier".to_string());
         }
     }
 
     let mut seen = std::collections::HashSet::new();
     pool.retain(|p| {
-        let key = format!("{}:{}", p.ip, p.port);
+        let key = p.key();
         if seen.contains(&key) {
             false
         } else {
@@ -259,16 +656,21 @@ pub fn filter_mode_pool(
         }
     });
 
-    pool
+    pool.retain(|p| p.effective_score_at(&opts.scoring, now) >= floor);
+    if floor > 0.0 {
+        rules.push(format!("score floor: effective score >= {:.2}", floor));
+    }
+
+    (pool, rules)
 }
 
-fn generate_chain_id<R: Rng + ?Sized>(rng: &mut R) -> String {
+pub(crate) fn generate_chain_id<R: Rng + ?Sized>(rng: &mut R) -> String {
     let mut bytes = [0u8; 16];
     rng.fill_bytes(&mut bytes);
     hex::encode(bytes)
 }
 
-fn generate_key_nonce<R: Rng + ?Sized>(rng: &mut R) -> (String, String) {
+pub(crate) fn generate_key_nonce<R: Rng + ?Sized>(rng: &mut R) -> (String, String) {
     let mut key = [0u8; 32];
     let mut nonce = [0u8; 12];
     rng.fill_bytes(&mut key);
@@ -276,16 +678,59 @@ fn generate_key_nonce<R: Rng + ?Sized>(rng: &mut R) -> (String, String) {
     (hex::encode(key), hex::encode(nonce))
 }
 
-fn get_subnet(ip: &str) -> String {
-    let parts: Vec<&str> = ip.split('.').collect();
-    if parts.len() >= 3 {
-        format!("{}.{}.{}", parts[0], parts[1], parts[2])
-    } else {
-        ip.to_string()
+/// Network a hop belongs to for anti-correlation: the /24 for IPv4 (including
+/// IPv4-mapped IPv6) and the /48 for IPv6. `None` for hostnames, which cannot
+/// be grouped without resolving them.
+pub fn ip_subnet_key(ip: &str) -> Option<String> {
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            Some(format!("{}.{}.{}.0/24", o[0], o[1], o[2]))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => ip_subnet_key(&v4.to_string()),
+            None => {
+                let s = v6.segments();
+                Some(format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2]))
+            }
+        },
+    }
+}
+
+/// A hop picked by a `ChainStrategy`: its index into the pool and how it was
+/// chosen.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HopPick {
+    pub index: usize,
+    pub selection: HopSelection,
+}
+
+/// The hops a `ChainStrategy` picked, entry hop first, and the chain-wide
+/// rules it applied. Both end up in the decision's `SelectionTrace`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainSelection {
+    pub hops: Vec<HopPick>,
+    pub rules: Vec<String>,
+}
+
+/// Bare pool indices, for strategies that do not record how they picked.
+impl From<Vec<usize>> for ChainSelection {
+    fn from(indices: Vec<usize>) -> Self {
+        ChainSelection {
+            hops: indices
+                .into_iter()
+                .map(|index| HopPick {
+                    index,
+                    selection: HopSelection::default(),
+                })
+                .collect(),
+            rules: Vec::new(),
+        }
     }
 }
 
-/// Weighted random selection of proxy indices based on their scores.
+/// Weighted random selection of proxy indices based on their effective scores.
 /// Higher score proxies are selected more often, but with diversity control.
 ///
 /// `pool` - the proxy pool to select from
@@ -296,48 +741,115 @@ fn get_subnet(ip: &str) -> String {
 ///   - >1.0 = more diversity (flattens the weight distribution)
 ///   - <1.0 = even stronger preference for top scores
 ///
-/// Returns indices of selected proxies (no duplicates).
-fn weighted_random_choice<R: Rng>(
+/// `opts` - `exit_weights` multiply the exit pick by `ip:port`, and
+/// `distinct_subnets` gates the subnet-uniqueness constraint
+/// `pinned_exit` - an exit-set hop that will follow the picks: its subnet
+/// counts as used, and no pick is treated as the exit
+///
+/// Without a pinned exit the exit is drawn first (so it is not limited to
+/// subnets the other hops left over) and returned last.
+///
+/// Returns the selected proxies (no duplicates), each with the number of
+/// candidates it was drawn from and the constraints applied to that pick.
+pub(crate) fn weighted_random_choice<R: Rng>(
     pool: &[Proxy],
     mut rng: R,
     num_to_select: usize,
     diversity_exponent: f64,
-) -> Vec<usize> {
-    let mut selected_indices = Vec::with_capacity(num_to_select);
+    opts: &ChainOptions,
+    pinned_exit: Option<&Proxy>,
+    now: u64,
+) -> Vec<HopPick> {
+    let mut picks = Vec::with_capacity(num_to_select);
     let mut available: Vec<usize> = (0..pool.len()).collect();
     let mut used_subnets = std::collections::HashSet::new();
+    if let Some(subnet) = pinned_exit.and_then(|p| ip_subnet_key(&p.ip)) {
+        used_subnets.insert(subnet);
+    }
 
-    for _ in 0..num_to_select {
+    for pick in 0..num_to_select {
         if available.is_empty() {
             break;
         }
+        let is_exit = pinned_exit.is_none() && pick == 0;
+        let mut constraints = Vec::new();
+        if is_exit {
+            constraints.push("exit hop, drawn before the other hops".to_string());
+        }
 
-        // Filter available to exclude same subnets
-        let filtered_available: Vec<usize> = available
-            .iter()
-            .cloned()
-            .filter(|&idx| !used_subnets.contains(&get_subnet(&pool[idx].ip)))
-            .collect();
+        // Filter available to exclude subnets already in the chain (/24 IPv4, /48 IPv6)
+        let filtered_available: Vec<usize> = if opts.distinct_subnets {
+            available
+                .iter()
+                .cloned()
+                .filter(|&idx| {
+                    ip_subnet_key(&pool[idx].ip).is_none_or(|k| !used_subnets.contains(&k))
+                })
+                .collect()
+        } else {
+            available.clone()
+        };
 
         // If no proxies from different subnets available, we have to relax the constraint
         // or stop. For anonymity, we prefer to stop or pick fewer hops if needed,
         // but for usability we'll use whatever is left if we can't meet diversity.
-        let final_pool = if filtered_available.is_empty() {
-            &available
+        let mut final_pool = if filtered_available.is_empty() {
+            constraints.push(
+                "subnet diversity relaxed: every remaining proxy shares a subnet with a chosen hop"
+                    .to_string(),
+            );
+            available.clone()
         } else {
-            &filtered_available
+            if filtered_available.len() < available.len() {
+                constraints.push(format!(
+                    "distinct subnet: {} proxies sharing a subnet with a chosen hop excluded",
+                    available.len() - filtered_available.len()
+                ));
+            }
+            filtered_available
         };
 
+        // A GET-only HTTP proxy cannot open the CONNECT tunnel to the target
+        if is_exit && opts.require_connect {
+            let connect_capable: Vec<usize> = final_pool
+                .iter()
+                .cloned()
+                .filter(|&idx| pool[idx].supports_connect)
+                .collect();
+            if connect_capable.is_empty() {
+                log::warn!("No CONNECT-capable exit available; keeping a GET-only exit");
+                constraints
+                    .push("CONNECT requirement relaxed: no CONNECT-capable proxy left".to_string());
+            } else {
+                constraints.push(format!(
+                    "CONNECT-capable exit: {} GET-only proxies excluded",
+                    final_pool.len() - connect_capable.len()
+                ));
+                final_pool = connect_capable;
+            }
+        }
+
         // Calculate weights for available proxies using diversity exponent
+        let affinity = |idx: usize| {
+            if is_exit {
+                opts.exit_weights
+                    .get(&pool[idx].key())
+                    .copied()
+                    .unwrap_or(1.0)
+            } else {
+                1.0
+            }
+        };
         let weights: Vec<f64> = final_pool
             .iter()
             .map(|&idx| {
-                let score = if pool[idx].score > 0.0 {
-                    pool[idx].score
+                let score = pool[idx].effective_score_at(&opts.scoring, now);
+                let score = if score > 0.0 {
+                    score
                 } else {
                     0.5
                 };
-                score.powf(1.0 / diversity_exponent)
+                score.powf(1.0 / diversity_exponent) * affinity(idx)
             })
             .collect();
 
@@ -360,47 +872,500 @@ fn weighted_random_choice<R: Rng>(
         };
 
         let chosen_pool_idx = final_pool[chosen_idx_in_final];
-        selected_indices.push(chosen_pool_idx);
-        used_subnets.insert(get_subnet(&pool[chosen_pool_idx].ip));
+        if is_exit && opts.exit_weights.contains_key(&pool[chosen_pool_idx].key()) {
+            constraints.push(format!(
+                "exit affinity weight {:.2}",
+                affinity(chosen_pool_idx)
+            ));
+        }
+        if total_weight <= 0.0 {
+            constraints.push("uniform draw (no positive weight)".to_string());
+        } else {
+            constraints.push(format!(
+                "score-weighted draw: {:.1}% chance",
+                weights[chosen_idx_in_final] / total_weight * 100.0
+            ));
+        }
+        picks.push(HopPick {
+            index: chosen_pool_idx,
+            selection: HopSelection {
+                candidates: final_pool.len(),
+                effective_score: Some(pool[chosen_pool_idx].effective_score_at(&opts.scoring, now)),
+                constraints,
+            },
+        });
+        if let Some(subnet) = ip_subnet_key(&pool[chosen_pool_idx].ip) {
+            used_subnets.insert(subnet);
+        }
         
         // Remove from global available list
         available.retain(|&x| x != chosen_pool_idx);
     }
 
-    selected_indices
+    if pinned_exit.is_none() && !picks.is_empty() {
+        picks.rotate_left(1);
+    }
+    picks
 }
 
-fn choose_chain_internal<R: Rng>(
-    mode: &str,
+/// Reorder selected hops so SOCKS4 sits at the end of the chain.
+///
+/// SOCKS4 can only CONNECT to an IPv4 address (4a adds hostnames, but only for
+/// the final target), so a SOCKS4 hop is kept as the terminal hop unless the hop
+/// after it is an IPv4 literal. Relative order is otherwise preserved. A
+/// `pinned_exit` always stays last, after every selected hop. Returns whether
+/// any hop was moved.
+fn place_socks4_hops(pool: &[Proxy], selected: &mut [usize], pinned_exit: Option<&Proxy>) -> bool {
+    let is_socks4 = |p: &Proxy| normalize_proto(&p.proto) == "socks4";
+    let next_ok = |p: &Proxy| p.ip.parse::<std::net::Ipv4Addr>().is_ok();
+
+    let valid = |sel: &[usize]| {
+        let hops: Vec<&Proxy> = sel.iter().map(|&i| &pool[i]).chain(pinned_exit).collect();
+        hops.windows(2).all(|w| !is_socks4(w[0]) || next_ok(w[1]))
+    };
+    if valid(selected) {
+        return false;
+    }
+    // Stable partition: non-SOCKS4 first, SOCKS4 last
+    selected.sort_by_key(|&i| is_socks4(&pool[i]));
+    if !valid(selected) {
+        log::warn!("SOCKS4 hop placed before a non-IPv4 hop; the handshake needs SOCKS4a");
+    }
+    true
+}
+
+/// Swap non-DNS hops for the best unused DNS-capable proxies until `required`
+/// selected hops are DNS-capable. Starts at the last selected hop, where the
+/// target name is resolved; a `pinned_exit` is never part of `selected`, so it
+/// is never swapped out (the caller counts its DNS capability in `required`).
+/// For the exit slot, candidates are ranked with their `exit_weights` applied.
+///
+/// Swapped-in proxies obey the same rules as sampled ones: a CONNECT-capable
+/// exit under `require_connect`, a hop set different from `previous_hops`, and
+/// a subnet no other hop uses when `distinct_subnets` is set (relaxed, as in
+/// the sampler, only when no candidate qualifies). Returns the swapped-in hops.
+fn promote_dns_hops(
     pool: &[Proxy],
-    mut rng: R,
-) -> Option<RotationDecision> {
-    if pool.is_empty() {
-        return None;
+    selected: &mut [usize],
+    required: usize,
+    opts: &ChainOptions,
+    pinned_exit: Option<&Proxy>,
+    now: u64,
+) -> Vec<HopPick> {
+    let mut promoted = Vec::new();
+    let mut spare: Vec<usize> = (0..pool.len())
+        .filter(|i| pool[*i].is_dns_capable() && !selected.contains(i))
+        .collect();
+    spare.sort_by(|a, b| {
+        pool[*b]
+            .effective_score_at(&opts.scoring, now)
+            .partial_cmp(&pool[*a].effective_score_at(&opts.scoring, now))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut have = selected
+        .iter()
+        .filter(|&&i| pool[i].is_dns_capable())
+        .count();
+    let last = selected.len().saturating_sub(1);
+    for slot in (0..selected.len()).rev() {
+        if have >= required {
+            break;
+        }
+        if pool[selected[slot]].is_dns_capable() {
+            continue;
+        }
+        let is_exit = pinned_exit.is_none() && slot == last;
+        let fits = |idx: usize, distinct: bool| {
+            let p = &pool[idx];
+            if is_exit && opts.require_connect && !p.supports_connect {
+                return false;
+            }
+            let others = || {
+                selected
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != slot)
+                    .map(|(_, &j)| &pool[j])
+                    .chain(pinned_exit)
+            };
+            if distinct {
+                if let Some(subnet) = ip_subnet_key(&p.ip) {
+                    if others().any(|q| ip_subnet_key(&q.ip).as_ref() == Some(&subnet)) {
+                        return false;
+                    }
+                }
+            }
+            let mut trial = selected.to_vec();
+            trial[slot] = idx;
+            opts.previous_hops.is_empty()
+                || !is_same_hop_set(pool, &trial, pinned_exit, &opts.previous_hops)
+        };
+        let rank = |idx: usize| {
+            let affinity = if is_exit {
+                opts.exit_weights
+                    .get(&pool[idx].key())
+                    .copied()
+                    .unwrap_or(1.0)
+            } else {
+                1.0
+            };
+            pool[idx].effective_score_at(&opts.scoring, now) * affinity
+        };
+        // First best-ranked fit; `spare` is already in score order
+        let best = |distinct: bool| {
+            spare
+                .iter()
+                .enumerate()
+                .filter(|&(_, &i)| fits(i, distinct))
+                .min_by(|a, b| {
+                    rank(*b.1)
+                        .partial_cmp(&rank(*a.1))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .map(|(pos, _)| pos)
+        };
+        let mut constraints = vec![format!(
+            "swapped in for a non-DNS hop to meet min_dns_hops ({})",
+            opts.min_dns_hops
+        )];
+        let mut distinct = opts.distinct_subnets;
+        let mut pos = best(distinct);
+        if pos.is_none() && distinct {
+            distinct = false;
+            pos = best(false);
+            if pos.is_some() {
+                constraints.push(
+                    "subnet diversity relaxed: every DNS-capable spare shares a subnet with a chosen hop"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(pos) = pos {
+            let candidates = spare.iter().filter(|&&i| fits(i, distinct)).count();
+            if is_exit && opts.require_connect {
+                constraints.push("CONNECT-capable exit".to_string());
+            }
+            if is_exit && opts.exit_weights.contains_key(&pool[spare[pos]].key()) {
+                constraints.push(format!(
+                    "highest effective score x exit affinity weight ({:.2})",
+                    opts.exit_weights[&pool[spare[pos]].key()]
+                ));
+            } else {
+                constraints.push("highest effective score among DNS-capable spares".to_string());
+            }
+            selected[slot] = spare.remove(pos);
+            promoted.push(HopPick {
+                index: selected[slot],
+                selection: HopSelection {
+                    candidates,
+                    effective_score: Some(
+                        pool[selected[slot]].effective_score_at(&opts.scoring, now),
+                    ),
+                    constraints,
+                },
+            });
+            have += 1;
+        }
+    }
+    promoted
+}
+
+/// Whether the selected pool indices, plus any pinned exit, form exactly the
+/// hop set in `previous`.
+fn is_same_hop_set(
+    pool: &[Proxy],
+    selected: &[usize],
+    pinned_exit: Option<&Proxy>,
+    previous: &[String],
+) -> bool {
+    if selected.len() + usize::from(pinned_exit.is_some()) != previous.len() {
+        return false;
     }
+    let mut current: Vec<String> = selected
+        .iter()
+        .map(|&i| &pool[i])
+        .chain(pinned_exit)
+        .map(Proxy::key)
+        .collect();
+    let mut previous = previous.to_vec();
+    current.sort();
+    previous.sort();
+    current == previous
+}
 
-    let (hops_min, hops_max) = match mode {
-        "phantom" => (3_usize, 5_usize),
+/// Minimum and maximum hop count for a mode.
+pub fn mode_hop_range(mode: &str) -> (usize, usize) {
+    match mode {
+        "phantom" => (3, 5),
         "high" => (2, 3),
         "stealth" => (1, 2),
         _ => (1, 1),
+    }
+}
+
+/// Picks the hops of a chain from the mode-filtered pool.
+///
+/// `select` returns indices into `pool`, entry hop first, with how each was
+/// picked (`ChainSelection::from` wraps bare indices), or `None` when it
+/// cannot produce a chain. With an active `ChainOptions::exit_set`,
+/// `pinned_exit` is the exit for this rotation: it already counts as one of
+/// the mode's hops and is appended after the selection, so the strategy picks
+/// only the hops before it. The rotator checks the result against the mode's
+/// minimum hop count and `ChainOptions::min_dns_hops`, then builds the decision
+/// (keys, chain id) the same way for every strategy. `now` is the build's
+/// single clock reading, for ranking with `Proxy::effective_score_at`.
+pub trait ChainStrategy {
+    fn select(
+        &self,
+        mode: &str,
+        pool: &[Proxy],
+        opts: &ChainOptions,
+        pinned_exit: Option<&Proxy>,
+        now: u64,
+        rng: &mut dyn RngCore,
+    ) -> Option<ChainSelection>;
+}
+
+/// The built-in strategy: a tier-scaled hop count (see `tier_scaled_hops`),
+/// score-weighted sampling with subnet diversity, reshuffles to avoid repeating
+/// the previous chain, and SOCKS4 hops moved to where their handshake works.
+/// The exit (the last pick, or the pinned exit) is never moved, so the
+/// `exit_weights` and `require_connect` rules applied to it hold.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultStrategy;
+
+impl ChainStrategy for DefaultStrategy {
+    fn select(
+        &self,
+        mode: &str,
+        pool: &[Proxy],
+        opts: &ChainOptions,
+        pinned_exit: Option<&Proxy>,
+        now: u64,
+        rng: &mut dyn RngCore,
+    ) -> Option<ChainSelection> {
+        let (hops_min, hops_max) = mode_hop_range(mode);
+        let hops = tier_scaled_hops(pool, hops_min, hops_max, &mut *rng);
+        // A pinned exit is one of the hops; only the ones before it are picked
+        let exit_hops = usize::from(pinned_exit.is_some());
+        let exit_dns = usize::from(pinned_exit.is_some_and(Proxy::is_dns_capable));
+        let picks = hops.saturating_sub(exit_hops).min(pool.len());
+        let dns_required = opts.min_dns_hops.min(picks + exit_hops);
+        let dns_hops =
+            |sel: &[usize]| sel.iter().filter(|&&i| pool[i].is_dns_capable()).count() + exit_dns;
+
+        // Use weighted selection based on proxy scores
+        // Diversity exponent of 1.5 provides a balance between preferring high scores
+        // and maintaining diversity in chain selection
+        let diversity_exponent = 1.5;
+        let choose = |rng: &mut dyn RngCore| {
+            weighted_random_choice(pool, rng, picks, diversity_exponent, opts, pinned_exit, now)
+        };
+        let indices = |picks: &[HopPick]| picks.iter().map(|p| p.index).collect::<Vec<_>>();
+        let mut sampled = choose(&mut *rng);
+        let mut selected = indices(&sampled);
+        let mut rules = vec![format!(
+            "{} hops for the pool's mean tier ({} mode allows {}-{})",
+            hops, mode, hops_min, hops_max
+        )];
+
+        // Unlinkability: consecutive rotations should differ whenever the pool has
+        // room for another hop set. Reshuffle too while short of DNS-capable hops.
+        let avoid_previous = !opts.previous_hops.is_empty() && pool.len() > picks;
+        let mut reshuffles = 0;
+        for _ in 0..MAX_RESHUFFLE_ATTEMPTS {
+            let repeats = avoid_previous
+                && is_same_hop_set(pool, &selected, pinned_exit, &opts.previous_hops);
+            if !repeats && dns_hops(&selected) >= dns_required {
+                break;
+            }
+            sampled = choose(&mut *rng);
+            selected = indices(&sampled);
+            reshuffles += 1;
+        }
+        if dns_hops(&selected) < dns_required {
+            sampled.extend(promote_dns_hops(
+                pool,
+                &mut selected,
+                dns_required - exit_dns,
+                opts,
+                pinned_exit,
+                now,
+            ));
+        }
+        if dns_required > 0 {
+            rules.push(format!(
+                "{} of {} required DNS-capable hops",
+                dns_hops(&selected),
+                dns_required
+            ));
+        }
+        if !opts.previous_hops.is_empty() {
+            rules.push(if !avoid_previous {
+                "pool too small to avoid the previous chain's hop set".to_string()
+            } else if is_same_hop_set(pool, &selected, pinned_exit, &opts.previous_hops) {
+                format!(
+                    "hop set repeats the previous chain after {} reshuffles",
+                    reshuffles
+                )
+            } else {
+                format!(
+                    "hop set differs from the previous chain ({} reshuffles)",
+                    reshuffles
+                )
+            });
+        }
+        let moved = match pinned_exit {
+            Some(exit) => place_socks4_hops(pool, &mut selected, Some(exit)),
+            None if selected.len() > 1 => {
+                let last = selected.len() - 1;
+                let (hops, exit) = selected.split_at_mut(last);
+                place_socks4_hops(pool, hops, Some(&pool[exit[0]]))
+            }
+            None => false,
+        };
+        if moved {
+            rules.push("SOCKS4 hops moved after hops their handshake cannot address".to_string());
+        }
+
+        // Promoted hops replace sampled ones, so the latest pick for an index wins
+        let hops = selected
+            .iter()
+            .map(|&i| {
+                sampled
+                    .iter()
+                    .rev()
+                    .find(|p| p.index == i)
+                    .cloned()
+                    .unwrap_or_else(|| HopPick {
+                        index: i,
+                        selection: HopSelection::default(),
+                    })
+            })
+            .collect();
+        Some(ChainSelection { hops, rules })
+    }
+}
+
+/// Draw a hop count in `hops_min..=hops_max` that grows with pool quality, so
+/// strong pools get longer chains and weak ones keep failure odds down.
+///
+/// The pool's mean tier maps linearly onto a quality `q` from 0.0 (bronze or
+/// worse) to 1.0 (platinum), and each hop above the minimum is added with
+/// probability `q`: an all-platinum pool always gets the upper bound, an
+/// all-bronze pool the lower bound, and mixed pools land in between at random.
+///
+/// Pools that were never polished (hand-written, imported or raw scraper
+/// output) carry no real tier: a missing `tier` deserializes as Bronze and the
+/// score is 0. When no proxy has been scored or tiered the draw is uniform over
+/// the range instead, so such pools are not pinned to the minimum.
+fn tier_scaled_hops(
+    pool: &[Proxy],
+    hops_min: usize,
+    hops_max: usize,
+    rng: &mut dyn RngCore,
+) -> usize {
+    if pool.is_empty() || hops_max <= hops_min {
+        return hops_min;
+    }
+    let tiered = |p: &Proxy| p.score > 0.0 || p.tier != ProxyTier::default();
+    if !pool.iter().any(tiered) {
+        return rng.gen_range(hops_min..=hops_max);
+    }
+    let mean_tier = pool.iter().map(|p| p.tier as u8 as f64).sum::<f64>() / pool.len() as f64;
+    let bronze = ProxyTier::Bronze as u8 as f64;
+    let platinum = ProxyTier::Platinum as u8 as f64;
+    let q = ((mean_tier - bronze) / (platinum - bronze)).clamp(0.0, 1.0);
+    hops_min + (hops_min..hops_max).filter(|_| rng.gen_bool(q)).count()
+}
+
+/// Build a decision from `pool` with `strategy`. A `pinned_exit` (from the
+/// exit set) counts toward the mode's minimum hop count and DNS-capable hops,
+/// and is appended as the last hop after the strategy's selection.
+fn choose_chain_internal<R: Rng>(
+    mode: &str,
+    pool: &[Proxy],
+    opts: &ChainOptions,
+    strategy: &dyn ChainStrategy,
+    pinned_exit: Option<&Proxy>,
+    now: u64,
+    mut rng: R,
+) -> Result<RotationDecision, ChainError> {
+    let (hops_min, _) = mode_hop_range(mode);
+    let exit_hops = usize::from(pinned_exit.is_some());
+    let needed = hops_min.saturating_sub(exit_hops);
+
+    if pool.is_empty() && needed > 0 {
+        return Err(ChainError::EmptyPool {
+            mode: mode.to_string(),
+        });
+    }
+
+    // Never hand out a chain shorter than the mode promises.
+    if pool.len() < needed {
+        return Err(ChainError::InsufficientProxies {
+            mode: mode.to_string(),
+            required: hops_min,
+            available: pool.len() + exit_hops,
+        });
+    }
+
+    let selection = strategy
+        .select(mode, pool, opts, pinned_exit, now, &mut rng)
+        .filter(|sel| {
+            let mut distinct: Vec<usize> = sel.hops.iter().map(|p| p.index).collect();
+            distinct.sort_unstable();
+            distinct.dedup();
+            sel.hops.len() + exit_hops >= hops_min
+                && distinct.len() == sel.hops.len()
+                && sel.hops.iter().all(|p| p.index < pool.len())
+        })
+        .ok_or_else(|| ChainError::NoSelection {
+            mode: mode.to_string(),
+        })?;
+    let hops: Vec<&Proxy> = selection
+        .hops
+        .iter()
+        .map(|p| &pool[p.index])
+        .chain(pinned_exit)
+        .collect();
+    let mut trace = SelectionTrace {
+        pool: pool.len(),
+        rules: selection.rules,
+        hops: selection.hops.into_iter().map(|p| p.selection).collect(),
     };
+    if let Some(exit) = pinned_exit {
+        trace.hops.push(HopSelection {
+            candidates: 1,
+            effective_score: Some(exit.effective_score_at(&opts.scoring, now)),
+            constraints: vec![format!(
+                "pinned exit {} of {} from the exit set (round robin)",
+                opts.exit_index % opts.exit_set.len().max(1) + 1,
+                opts.exit_set.len()
+            )],
+        });
+    }
 
-    let hops = rng.gen_range(hops_min..=hops_max).min(pool.len()).max(1);
+    let dns_required = opts.min_dns_hops.min(hops.len());
+    if hops.iter().filter(|p| p.is_dns_capable()).count() < dns_required {
+        return Err(ChainError::InsufficientDnsHops {
+            mode: mode.to_string(),
+            required: dns_required,
+            available: pool
+                .iter()
+                .chain(pinned_exit)
+                .filter(|p| p.is_dns_capable())
+                .count(),
+        });
+    }
 
-    // Use weighted selection based on proxy scores
-    // Diversity exponent of 1.5 provides a balance between preferring high scores
-    // and maintaining diversity in chain selection
-    let diversity_exponent = 1.5;
-    let selected = weighted_random_choice(pool, &mut rng, hops, diversity_exponent);
-    let mut chain = Vec::with_capacity(hops);
-    let mut crypto = Vec::with_capacity(hops);
+    let mut chain = Vec::with_capacity(hops.len());
+    let mut crypto = Vec::with_capacity(if opts.with_encryption { hops.len() } else { 0 });
     let mut sum_latency = 0.0_f64;
     let mut min_score = f64::INFINITY;
     let mut max_score = f64::NEG_INFINITY;
 
-    for idx in selected {
-        let p = &pool[idx];
+    for p in hops {
         let hop = ChainHop {
             ip: p.ip.clone(),
             port: p.port,
@@ -418,8 +1383,10 @@ fn choose_chain_internal<R: Rng>(
             max_score = hop.score;
         }
 
-        let (key_hex, nonce_hex) = generate_key_nonce(&mut rng);
-        crypto.push(CryptoHop { key_hex, nonce_hex });
+        if opts.with_encryption {
+            let (key_hex, nonce_hex) = generate_key_nonce(&mut rng);
+            crypto.push(CryptoHop { key_hex, nonce_hex });
+        }
 
         chain.push(hop);
     }
@@ -429,12 +1396,13 @@ fn choose_chain_internal<R: Rng>(
     let mut outer_rng = rng;
     let chain_id = generate_chain_id(&mut outer_rng);
 
-    Some(RotationDecision {
+    Ok(RotationDecision {
         mode: mode.to_string(),
-        timestamp: now_unix(),
+        timestamp: now,
         chain_id,
         chain,
         avg_latency,
+        total_latency: sum_latency,
         min_score: if min_score.is_finite() {
             min_score
         } else {
@@ -447,29 +1415,386 @@ fn choose_chain_internal<R: Rng>(
         },
         encryption: crypto,
         garlic: false,
+        selection: Some(trace),
     })
 }
 
+/// Parse an exit set spec: comma-separated `[proto://]ip:port` entries.
+/// The protocol defaults to socks5; IPv6 addresses must be bracketed.
+pub fn parse_exit_set(spec: &str) -> anyhow::Result<Vec<HopInfo>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|entry| {
+            let (proto, addr) = entry.split_once("://").unwrap_or(("socks5", entry));
+            let (host, port) = addr
+                .rsplit_once(':')
+                .ok_or_else(|| anyhow::anyhow!("exit '{}' is missing a port", entry))?;
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            let port: u16 = port
+                .parse()
+                .map_err(|_| anyhow::anyhow!("exit '{}' has an invalid port", entry))?;
+            if host.is_empty() || port == 0 {
+                anyhow::bail!("exit '{}' is not a valid ip:port", entry);
+            }
+            Ok(HopInfo {
+                ip: host.to_string(),
+                port,
+                proto: normalize_proto(proto),
+            })
+        })
+        .collect()
+}
+
+/// Round-robin position following `last_exit` (an `ip:port` key) in `exit_set`.
+/// Starts from 0 when there was no previous exit or it is not in the set.
+pub fn next_exit_index(exit_set: &[HopInfo], last_exit: Option<&str>) -> usize {
+    last_exit
+        .and_then(|k| {
+            exit_set
+                .iter()
+                .position(|e| endpoint_key(&e.ip, e.port) == k)
+        })
+        .map(|i| (i + 1) % exit_set.len())
+        .unwrap_or(0)
+}
+
+/// Score an exit without a pool record is reported (and floor-checked) with.
+const UNKNOWN_EXIT_SCORE: f64 = 0.5;
+
+/// The pinned exit as a pool proxy: its pool record when it has one (country,
+/// latency, score), else a bare record scored `UNKNOWN_EXIT_SCORE` as of now,
+/// so age decay does not discount the stand-in score. The protocol is always
+/// the one given in the exit set.
+fn pinned_exit_proxy(exit: &HopInfo, record: Option<&Proxy>) -> Proxy {
+    let mut proxy = record.cloned().unwrap_or_else(|| Proxy {
+        ip: exit.ip.clone(),
+        port: exit.port,
+        proto: String::new(),
+        latency: 0.0,
+        jitter: 0.0,
+        country: String::new(),
+        anonymity: String::new(),
+        score: UNKNOWN_EXIT_SCORE,
+        tier: ProxyTier::from_score(UNKNOWN_EXIT_SCORE),
+        fail_count: 0,
+        last_verified: now_unix(),
+        alive: true,
+        source_type: "standard".to_string(),
+        probes_total: 0,
+        probes_ok: 0,
+        supports_connect: true,
+    });
+    proxy.proto = normalize_proto(&exit.proto);
+    proxy
+}
+
 pub fn build_chain_decision(
     mode: &str,
     dns: &[Proxy],
     non_dns: &[Proxy],
     combined: &[Proxy],
-) -> Option<RotationDecision> {
-    let pool = filter_mode_pool(mode, dns, non_dns, combined);
-    if pool.is_empty() {
-        return None;
-    }
-
-    let mut rng = StdRng::from_entropy();
-    choose_chain_internal(mode, &pool, &mut rng)
+) -> Result<RotationDecision, ChainError> {
+    build_chain_decision_with_options(mode, dns, non_dns, combined, &ChainOptions::default())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::types::ProxyTier;
-    use crate::types::HopInfo;
+/// Same as `build_chain_decision`, but with explicit chain construction options.
+///
+/// With `opts.with_encryption == false` no key material is drawn from the RNG and
+/// the returned decision has an empty `encryption` vector (plain SOCKS chain).
+pub fn build_chain_decision_with_options(
+    mode: &str,
+    dns: &[Proxy],
+    non_dns: &[Proxy],
+    combined: &[Proxy],
+    opts: &ChainOptions,
+) -> Result<RotationDecision, ChainError> {
+    build_chain_decision_with_strategy(mode, dns, non_dns, combined, opts, &DefaultStrategy)
+}
+
+/// Modes from strongest to weakest, the order `build_chain_decision_degrading`
+/// steps down through.
+const MODE_LADDER: [&str; 4] = ["phantom", "high", "stealth", "lite"];
+
+/// Like `build_chain_decision_with_options`, but when `mode` cannot be
+/// satisfied retries with each weaker mode in turn (phantom, high, stealth,
+/// lite). The decision's `mode` is the one actually used. If every mode fails
+/// the error for the requested mode is returned.
+pub fn build_chain_decision_degrading(
+    mode: &str,
+    dns: &[Proxy],
+    non_dns: &[Proxy],
+    combined: &[Proxy],
+    opts: &ChainOptions,
+) -> Result<RotationDecision, ChainError> {
+    let first_err = match build_chain_decision_with_options(mode, dns, non_dns, combined, opts) {
+        Ok(d) => return Ok(d),
+        Err(e) => e,
+    };
+    let weaker = MODE_LADDER
+        .iter()
+        .position(|m| *m == mode)
+        .map_or(&[][..], |i| &MODE_LADDER[i + 1..]);
+    for fallback in weaker {
+        if let Ok(d) = build_chain_decision_with_options(fallback, dns, non_dns, combined, opts) {
+            log::warn!(
+                "Mode '{}' unavailable ({}); degraded to '{}'",
+                mode,
+                first_err,
+                fallback
+            );
+            return Ok(d);
+        }
+    }
+    Err(first_err)
+}
+
+/// Same as `build_chain_decision_with_options`, with hop selection delegated to
+/// `strategy` instead of `DefaultStrategy`.
+pub fn build_chain_decision_with_strategy(
+    mode: &str,
+    dns: &[Proxy],
+    non_dns: &[Proxy],
+    combined: &[Proxy],
+    opts: &ChainOptions,
+    strategy: &dyn ChainStrategy,
+) -> Result<RotationDecision, ChainError> {
+    let country_error = || ChainError::CountryFiltered {
+        mode: mode.to_string(),
+        include: opts.include_countries.clone(),
+        exclude: opts.exclude_countries.clone(),
+    };
+    let now = now_unix();
+    let (mut pool, mut rules) = mode_pool(mode, dns, non_dns, combined, opts, now);
+    let mut pinned_exit = None;
+    if !opts.exit_set.is_empty() {
+        let exit = &opts.exit_set[opts.exit_index % opts.exit_set.len()];
+        let exit_record = combined
+            .iter()
+            .chain(dns)
+            .chain(non_dns)
+            .find(|p| is_endpoint(p, &exit.ip, exit.port));
+        let exit_country = exit_record.map_or("", |p| p.country.as_str());
+        if opts.has_country_filter() && !opts.country_allowed(exit_country) {
+            let decision = Err(country_error());
+            record_build(mode, &decision);
+            return decision;
+        }
+        let exit_proxy = pinned_exit_proxy(exit, exit_record);
+        if exit_proxy.effective_score_at(&opts.scoring, now) < mode_score_floor(mode, opts) {
+            let decision = Err(ChainError::ExitBelowFloor {
+                mode: mode.to_string(),
+                exit: endpoint_key(&exit.ip, exit.port),
+            });
+            record_build(mode, &decision);
+            return decision;
+        }
+        // Exits come from the fixed set only; keep them out of entry/middle positions
+        pool.retain(|p| !opts.exit_set.iter().any(|e| is_endpoint(p, &e.ip, e.port)));
+        rules.push(format!(
+            "exit pinned to {}; exit-set proxies kept out of other positions",
+            exit_proxy.key()
+        ));
+        pinned_exit = Some(exit_proxy);
+    }
+
+    let mut rng = StdRng::from_entropy();
+    let decision = choose_chain_internal(
+        mode,
+        &pool,
+        opts,
+        strategy,
+        pinned_exit.as_ref(),
+        now,
+        &mut rng,
+    );
+    let decision = match decision {
+        Ok(mut d) => {
+            if let Some(trace) = d.selection.as_mut() {
+                rules.append(&mut trace.rules);
+                trace.rules = rules;
+            }
+            Ok(d)
+        }
+        Err(ChainError::EmptyPool { .. }) if opts.has_country_filter() => Err(country_error()),
+        other => other,
+    };
+    record_build(mode, &decision);
+    decision
+}
+
+/// Emit the decision as structured `tracing` events for post-hoc analysis: one
+/// `chain_decision` event with the chain-level fields, then one `chain_hop`
+/// event per hop. Only topology and quality fields are logged, never the
+/// `encryption` key material.
+pub fn log_decision(decision: &RotationDecision) {
+    tracing::info!(
+        event = "chain_decision",
+        chain_id = %decision.chain_id,
+        mode = %decision.mode,
+        hops = decision.chain.len(),
+        total_latency = decision.total_latency,
+        min_score = decision.min_score,
+        encrypted = !decision.encryption.is_empty(),
+        "chain decision"
+    );
+    for (position, hop) in decision.chain.iter().enumerate() {
+        tracing::info!(
+            event = "chain_hop",
+            chain_id = %decision.chain_id,
+            position,
+            ip = %hop.ip,
+            port = hop.port,
+            proto = %hop.proto,
+            country = %hop.country,
+            score = hop.score,
+            latency = hop.latency,
+            tier = ?ProxyTier::from_score(hop.score),
+            "chain hop"
+        );
+    }
+}
+
+/// Why a chain looks the way it does, read from the decision's
+/// `SelectionTrace`. Produced by `explain_decision`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainExplanation {
+    pub chain_id: String,
+    pub mode: String,
+    /// Whether the decision carried a selection record; without one (e.g. a
+    /// known-good fallback) only the hops' inputs are known
+    pub traced: bool,
+    /// Proxies in the mode pool the hops were drawn from
+    pub pool: usize,
+    /// Chain-wide rules the chooser applied
+    pub rules: Vec<String>,
+    pub hops: Vec<HopExplanation>,
+}
+
+/// Why a hop ended up in a chain: its selection inputs and the constraints
+/// applied when it was picked.
+#[derive(Debug, Clone, Serialize)]
+pub struct HopExplanation {
+    pub position: usize,
+    pub ip: String,
+    pub port: u16,
+    pub proto: String,
+    pub country: String,
+    pub latency: f64,
+    pub score: f64,
+    /// Selection weight used by the sampler (`Proxy::effective_score`)
+    pub effective_score: f64,
+    pub tier: ProxyTier,
+    /// Eligible proxies the hop was drawn from (0 when not recorded)
+    pub candidates: usize,
+    pub constraints: Vec<String>,
+}
+
+impl fmt::Display for ChainExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.traced {
+            write!(f, "pool of {} proxies", self.pool)?;
+            for r in &self.rules {
+                write!(f, "\n  - {}", r)?;
+            }
+        } else {
+            write!(f, "no selection record (not built by the chooser)")?;
+        }
+        for hop in &self.hops {
+            write!(f, "\n{}", hop)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for HopExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hop {} {}://{}:{} score={:.3} effective={:.3} tier={:?} latency={:.3} country={}",
+            self.position + 1,
+            self.proto,
+            self.ip,
+            self.port,
+            self.score,
+            self.effective_score,
+            self.tier,
+            self.latency,
+            if self.country.is_empty() {
+                "?"
+            } else {
+                &self.country
+            },
+        )?;
+        if self.candidates > 0 {
+            write!(f, " (1 of {} candidates)", self.candidates)?;
+        }
+        for c in &self.constraints {
+            write!(f, "\n    - {}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Explain `decision` from the selection record the chooser stored on it: the
+/// chain-wide rules, and per hop the candidate count and the constraints that
+/// were actually applied. The pools are only consulted for each hop's current
+/// effective score and tier.
+pub fn explain_decision(
+    decision: &RotationDecision,
+    dns: &[Proxy],
+    non_dns: &[Proxy],
+    combined: &[Proxy],
+) -> ChainExplanation {
+    let trace = decision.selection.as_ref();
+    let hops = decision
+        .chain
+        .iter()
+        .enumerate()
+        .map(|(i, hop)| {
+            let record = combined
+                .iter()
+                .chain(dns)
+                .chain(non_dns)
+                .find(|p| is_endpoint(p, &hop.ip, hop.port));
+            let selection = trace
+                .and_then(|t| t.hops.get(i))
+                .cloned()
+                .unwrap_or_default();
+            HopExplanation {
+                position: i,
+                ip: hop.ip.clone(),
+                port: hop.port,
+                proto: hop.proto.clone(),
+                country: hop.country.clone(),
+                latency: hop.latency,
+                score: hop.score,
+                effective_score: selection
+                    .effective_score
+                    .or_else(|| record.map(|p| p.effective_score()))
+                    .unwrap_or(hop.score),
+                tier: record
+                    .map(|p| p.tier)
+                    .unwrap_or_else(|| ProxyTier::from_score(hop.score)),
+                candidates: selection.candidates,
+                constraints: selection.constraints,
+            }
+        })
+        .collect();
+    ChainExplanation {
+        chain_id: decision.chain_id.clone(),
+        mode: decision.mode.clone(),
+        traced: trace.is_some(),
+        pool: trace.map_or(0, |t| t.pool),
+        rules: trace.map(|t| t.rules.clone()).unwrap_or_default(),
+        hops,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProxyTier;
     use rand::SeedableRng;
 
     /// Helper to create a test proxy
@@ -487,14 +1812,18 @@ mod tests {
             port,
             proto: proto.to_string(),
             latency,
+            jitter: 0.0,
             country: country.to_string(),
             anonymity: anonymity.to_string(),
             score,
             tier: ProxyTier::from_score(score),
             fail_count: 0,
-            last_verified: 0,
+            last_verified: now_unix(),
             alive: true,
             source_type: "standard".to_string(),
+            probes_total: 0,
+            probes_ok: 0,
+            supports_connect: true,
         }
     }
 
@@ -610,6 +1939,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_filter_mode_high_socks4_opt_in() {
+        // SOCKS4 is excluded from high mode unless the mode's protocol set allows it
+        let dns = vec![make_dns_proxy("192.168.1.1", 8080, "https", 0.8)];
+        let non_dns: Vec<Proxy> = vec![];
+        let combined = vec![
+            make_proxy("10.0.1.1", 1080, "socks4", 100.0, "us", "elite", 0.6),
+            make_proxy("10.0.2.1", 1080, "socks4", 100.0, "us", "elite", 0.6),
+        ];
+
+        let pool = filter_mode_pool("high", &dns, &non_dns, &combined);
+        assert!(pool.iter().all(|p| p.proto != "socks4"));
+
+        let mut opts = ChainOptions::default();
+        opts.mode_protocols.insert(
+            "high".to_string(),
+            vec!["https".into(), "socks5".into(), "socks4".into()],
+        );
+
+        let decision = build_chain_decision_with_options("high", &dns, &non_dns, &combined, &opts);
+        assert!(decision.is_ok());
+        let decision = decision.unwrap();
+        assert!(
+            decision.chain.iter().any(|h| h.proto == "socks4"),
+            "Opted-in SOCKS4 proxy should appear in a high-mode chain"
+        );
+    }
+
     #[test]
     fn test_filter_mode_unknown_defaults_to_all() {
         // Unknown mode should default to including all proxies
@@ -646,25 +2003,25 @@ mod tests {
 
         // Test lite mode (1 hop)
         let lite_decision = build_chain_decision("lite", &dns, &non_dns, &combined);
-        assert!(lite_decision.is_some());
+        assert!(lite_decision.is_ok());
         let lite = lite_decision.unwrap();
         assert!(
-            lite.chain.len() >= 1 && lite.chain.len() <= 1,
+            !lite.chain.is_empty() && lite.chain.len() <= 1,
             "Lite mode should have 1 hop"
         );
 
         // Test stealth mode (1-2 hops)
         let stealth_decision = build_chain_decision("stealth", &dns, &non_dns, &combined);
-        assert!(stealth_decision.is_some());
+        assert!(stealth_decision.is_ok());
         let stealth = stealth_decision.unwrap();
         assert!(
-            stealth.chain.len() >= 1 && stealth.chain.len() <= 2,
+            !stealth.chain.is_empty() && stealth.chain.len() <= 2,
             "Stealth mode should have 1-2 hops"
         );
 
         // Test high mode (2-3 hops)
         let high_decision = build_chain_decision("high", &dns, &non_dns, &combined);
-        assert!(high_decision.is_some());
+        assert!(high_decision.is_ok());
         let high = high_decision.unwrap();
         assert!(
             high.chain.len() >= 2 && high.chain.len() <= 3,
@@ -673,7 +2030,7 @@ mod tests {
 
         // Test phantom mode (3-5 hops)
         let phantom_decision = build_chain_decision("phantom", &dns, &non_dns, &combined);
-        assert!(phantom_decision.is_some());
+        assert!(phantom_decision.is_ok());
         let phantom = phantom_decision.unwrap();
         assert!(
             phantom.chain.len() >= 3 && phantom.chain.len() <= 5,
@@ -697,7 +2054,7 @@ mod tests {
         // Run multiple times to check for uniqueness
         for _ in 0..10 {
             let decision = build_chain_decision("phantom", &dns, &non_dns, &combined);
-            assert!(decision.is_some());
+            assert!(decision.is_ok());
             let decision = decision.unwrap();
 
             // Check that all hops have unique IP:port combinations
@@ -721,7 +2078,7 @@ mod tests {
         let combined: Vec<Proxy> = vec![];
 
         let decision = build_chain_decision("lite", &dns, &non_dns, &combined);
-        assert!(decision.is_some());
+        assert!(decision.is_ok());
         let decision = decision.unwrap();
 
         // Check that all encryption keys are valid 64-char hex (32 bytes)
@@ -761,7 +2118,7 @@ mod tests {
         let combined: Vec<Proxy> = vec![];
 
         let decision = build_chain_decision("lite", &dns, &non_dns, &combined);
-        assert!(decision.is_some());
+        assert!(decision.is_ok());
         let decision = decision.unwrap();
 
         // Chain ID should be 32 hex chars (16 bytes)
@@ -776,15 +2133,369 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_chain_without_encryption() {
+        // Plain chains carry no key material but still have hops
+        let dns = vec![
+            make_dns_proxy("192.168.1.1", 8080, "https", 0.8),
+            make_dns_proxy("192.168.2.1", 8081, "socks5", 0.7),
+            make_dns_proxy("192.168.3.1", 8082, "https", 0.9),
+        ];
+        let non_dns: Vec<Proxy> = vec![];
+        let combined: Vec<Proxy> = vec![];
+        let opts = ChainOptions {
+            with_encryption: false,
+            ..ChainOptions::default()
+        };
+
+        let decision = build_chain_decision_with_options("high", &dns, &non_dns, &combined, &opts);
+        assert!(decision.is_ok());
+        let decision = decision.unwrap();
+
+        assert!(
+            decision.encryption.is_empty(),
+            "Plain chain should have no keys"
+        );
+        assert!(
+            decision.chain.len() >= 2,
+            "Plain chain should still have hops"
+        );
+    }
+
+    #[test]
+    fn test_consecutive_rotations_differ() {
+        let dns: Vec<Proxy> = (1..=6)
+            .map(|i| make_dns_proxy(&format!("10.0.{}.1", i), 1080, "socks5", 0.8))
+            .collect();
+        let pool = filter_mode_pool("lite", &dns, &[], &[]);
+
+        // Seeded, so a run cannot pass or fail by chance
+        let mut rng = StdRng::seed_from_u64(1780);
+        let mut opts = ChainOptions::default();
+        for _ in 0..20 {
+            let decision = choose_chain_internal(
+                "lite",
+                &pool,
+                &opts,
+                &DefaultStrategy,
+                None,
+                now_unix(),
+                &mut rng,
+            )
+            .unwrap();
+            let hops: Vec<String> = decision
+                .chain
+                .iter()
+                .map(|h| format!("{}:{}", h.ip, h.port))
+                .collect();
+            assert_ne!(
+                hops, opts.previous_hops,
+                "Consecutive rotations should not repeat the same chain"
+            );
+            opts.previous_hops = hops;
+        }
+    }
+
     #[test]
     fn test_build_chain_decision_empty_pool() {
-        // Should return None when pool is empty
+        // Should report an empty pool when no proxies are eligible
         let dns: Vec<Proxy> = vec![];
         let non_dns: Vec<Proxy> = vec![];
         let combined: Vec<Proxy> = vec![];
 
         let decision = build_chain_decision("lite", &dns, &non_dns, &combined);
-        assert!(decision.is_none(), "Should return None for empty pool");
+        assert_eq!(
+            decision.unwrap_err(),
+            ChainError::EmptyPool {
+                mode: "lite".to_string()
+            },
+            "Should report an empty pool"
+        );
+    }
+
+    #[test]
+    fn test_phantom_rejects_pool_below_minimum_hops() {
+        // Two eligible proxies cannot make a 3-hop phantom chain
+        let dns = vec![
+            make_dns_proxy("192.168.1.1", 8080, "https", 0.9),
+            make_dns_proxy("192.168.2.1", 8081, "socks5", 0.9),
+        ];
+        let non_dns: Vec<Proxy> = vec![];
+        let combined = dns.clone();
+
+        let before = chain_build_failures();
+        let decision = build_chain_decision("phantom", &dns, &non_dns, &combined);
+        assert_eq!(
+            decision.unwrap_err(),
+            ChainError::InsufficientProxies {
+                mode: "phantom".to_string(),
+                required: 3,
+                available: 2,
+            }
+        );
+        assert!(chain_build_failures().unsatisfiable > before.unsatisfiable);
+
+        // The same pool still satisfies high mode's 2-hop minimum
+        let high = build_chain_decision("high", &dns, &non_dns, &combined).unwrap();
+        assert_eq!(high.chain.len(), 2);
+    }
+
+    #[test]
+    fn test_explain_decision_lists_each_hop() {
+        let dns = vec![
+            make_proxy("192.168.1.1", 8080, "https", 100.0, "us", "elite", 0.9),
+            make_proxy("192.168.2.1", 8081, "socks5", 100.0, "de", "elite", 0.8),
+            make_proxy("192.168.3.1", 8082, "https", 100.0, "nl", "elite", 0.85),
+            make_proxy("192.168.4.1", 8083, "socks5", 100.0, "fr", "elite", 0.75),
+        ];
+        let non_dns: Vec<Proxy> = vec![];
+        let combined = dns.clone();
+        let opts = ChainOptions {
+            previous_hops: vec!["192.168.1.1:8080".to_string()],
+            ..ChainOptions::default()
+        };
+
+        let decision =
+            build_chain_decision_with_options("phantom", &dns, &non_dns, &combined, &opts).unwrap();
+        let explanation = explain_decision(&decision, &dns, &non_dns, &combined);
+
+        assert!(explanation.traced);
+        assert_eq!(explanation.pool, 4);
+        let has_rule = |prefix: &str| explanation.rules.iter().any(|r| r.starts_with(prefix));
+        assert!(has_rule("source: DNS-capable Gold+"));
+        assert!(has_rule("score floor: effective score >= 0.50"));
+        let n = decision.chain.len();
+        // A chain using the whole pool has no other hop set to move to
+        if n < 4 {
+            assert!(has_rule("hop set differs from the previous chain"));
+        } else {
+            assert!(has_rule("pool too small to avoid the previous chain"));
+        }
+
+        assert_eq!(explanation.hops.len(), n);
+        for (i, (e, hop)) in explanation.hops.iter().zip(&decision.chain).enumerate() {
+            assert_eq!((e.ip.as_str(), e.port), (hop.ip.as_str(), hop.port));
+            assert_eq!(e.score, hop.score);
+            // The exit is drawn first from the whole pool, then each hop from
+            // what is left; every subnet is distinct, so none is excluded
+            let exit = i + 1 == n;
+            assert_eq!(e.candidates, if exit { 4 } else { 3 - i });
+            assert_eq!(
+                e.constraints.iter().any(|c| c.starts_with("exit hop")),
+                exit
+            );
+            assert!(e
+                .constraints
+                .iter()
+                .any(|c| c.starts_with("score-weighted draw")));
+            assert!(!e.constraints.iter().any(|c| c.contains("subnet")));
+            assert!(!e.constraints.iter().any(|c| c.contains("country")));
+            assert!(e.to_string().contains(&format!("score={:.3}", hop.score)));
+        }
+
+        // Decisions not built by the chooser carry no record to explain
+        let mut replayed = decision.clone();
+        replayed.selection = None;
+        let explanation = explain_decision(&replayed, &dns, &non_dns, &combined);
+        assert!(!explanation.traced);
+        assert!(explanation.hops.iter().all(|e| e.constraints.is_empty()));
+    }
+
+    #[test]
+    fn test_selection_trace_records_relaxed_constraints() {
+        // Two proxies in one /24: the second pick cannot avoid the first's subnet
+        let pool = vec![
+            make_dns_proxy("192.168.1.1", 1080, "socks5", 0.9),
+            make_dns_proxy("192.168.1.2", 1080, "socks5", 0.9),
+        ];
+        let mut rng = StdRng::seed_from_u64(1790);
+        let picks = weighted_random_choice(
+            &pool,
+            &mut rng,
+            2,
+            1.0,
+            &ChainOptions::default(),
+            None,
+            now_unix(),
+        );
+        assert!(picks[0].selection.constraints[0].starts_with("subnet diversity relaxed"));
+        assert_eq!(picks[0].selection.candidates, 1);
+        assert_eq!(picks[1].selection.candidates, 2);
+
+        // With the constraint off nothing about subnets is claimed
+        let loose = ChainOptions {
+            distinct_subnets: false,
+            ..ChainOptions::default()
+        };
+        let picks = weighted_random_choice(&pool, &mut rng, 2, 1.0, &loose, None, now_unix());
+        assert!(picks.iter().all(|p| !p
+            .selection
+            .constraints
+            .iter()
+            .any(|c| c.contains("subnet"))));
+    }
+
+    #[test]
+    fn test_socks4_hop_stays_terminal() {
+        let pool = vec![
+            make_dns_proxy("192.168.1.1", 1080, "socks4", 0.9),
+            make_dns_proxy("proxy.example.com", 1080, "socks5", 0.9),
+            make_dns_proxy("192.168.3.1", 443, "https", 0.9),
+        ];
+
+        // socks4 ahead of a hostname hop is moved to the end
+        let mut selected = vec![0, 1, 2];
+        place_socks4_hops(&pool, &mut selected, None);
+        assert_eq!(selected, vec![1, 2, 0]);
+
+        // socks4 followed by an IPv4 hop is left alone
+        let mut selected = vec![0, 2, 1];
+        place_socks4_hops(&pool, &mut selected, None);
+        assert_eq!(selected, vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn test_exit_weight_applies_to_final_exit() {
+        // SOCKS4 hops ahead of a hostname exit would once be moved behind it,
+        // taking the exit slot away from the weighted pick
+        let pool = vec![
+            make_dns_proxy("192.168.1.1", 1080, "socks4", 0.9),
+            make_dns_proxy("192.168.2.1", 1080, "socks4", 0.9),
+            make_dns_proxy("proxy.example.com", 1080, "socks5", 0.9),
+            make_dns_proxy("192.168.4.1", 443, "https", 0.9),
+        ];
+        let mut opts = ChainOptions::default();
+        opts.mode_protocols.insert(
+            "high".to_string(),
+            vec!["https".into(), "socks5".into(), "socks4".into()],
+        );
+        opts.exit_weights
+            .insert("proxy.example.com:1080".to_string(), 1e6);
+
+        for _ in 0..50 {
+            let d = build_chain_decision_with_options("high", &pool, &[], &pool, &opts).unwrap();
+            assert_eq!(d.chain.last().unwrap().ip, "proxy.example.com");
+        }
+    }
+
+    #[test]
+    fn test_exit_set_round_robin() {
+        let dns = vec![
+            make_dns_proxy("192.168.1.1", 8080, "https", 0.9),
+            make_dns_proxy("192.168.2.1", 8081, "socks5", 0.8),
+            make_dns_proxy("192.168.3.1", 8082, "https", 0.85),
+            make_dns_proxy("192.168.4.1", 8083, "socks5", 0.7),
+        ];
+        let non_dns: Vec<Proxy> = vec![];
+        let combined = dns.clone();
+
+        // One exit is also a pool proxy, the others are outside the pool
+        let exit_set = parse_exit_set("10.0.0.1:1080, https://192.168.4.1:8083,[2001:db8::1]:1080")
+            .expect("valid exit set");
+        assert_eq!(exit_set.len(), 3);
+        assert_eq!(exit_set[0].proto, "socks5");
+        assert_eq!(exit_set[2].ip, "2001:db8::1");
+
+        let mut last_exit: Option<String> = None;
+        for round in 0..7 {
+            let opts = ChainOptions {
+                exit_set: exit_set.clone(),
+                exit_index: next_exit_index(&exit_set, last_exit.as_deref()),
+                ..ChainOptions::default()
+            };
+            let decision =
+                build_chain_decision_with_options("high", &dns, &non_dns, &combined, &opts)
+                    .expect("chain should build");
+
+            let expected = &exit_set[round % exit_set.len()];
+            let exit = decision.chain.last().unwrap();
+            assert_eq!(
+                (exit.ip.as_str(), exit.port),
+                (expected.ip.as_str(), expected.port)
+            );
+            // Exits never double as entry or middle hops
+            for hop in &decision.chain[..decision.chain.len() - 1] {
+                assert!(!exit_set
+                    .iter()
+                    .any(|e| e.ip == hop.ip && e.port == hop.port));
+            }
+            last_exit = Some(format!("{}:{}", exit.ip, exit.port));
+        }
+
+        assert!(parse_exit_set("10.0.0.1").is_err());
+        assert!(parse_exit_set("10.0.0.1:0").is_err());
+    }
+
+    #[test]
+    fn test_pinned_exit_counts_as_a_hop() {
+        let dns = vec![make_dns_proxy("10.0.1.1", 1080, "socks5", 0.9)];
+        let opts = ChainOptions {
+            exit_set: parse_exit_set("10.0.9.1:1080").unwrap(),
+            ..ChainOptions::default()
+        };
+
+        // One pool proxy plus the exit meets high mode's two-hop minimum
+        let d = build_chain_decision_with_options("high", &dns, &[], &dns, &opts).unwrap();
+        let ips: Vec<&str> = d.chain.iter().map(|h| h.ip.as_str()).collect();
+        assert_eq!(ips, vec!["10.0.1.1", "10.0.9.1"]);
+        assert_eq!(d.encryption.len(), 2);
+
+        // The exit is appended after the strategy's picks, never over one
+        let pool: Vec<Proxy> = (1..=3)
+            .map(|i| {
+                make_proxy(
+                    &format!("10.0.{}.1", i),
+                    1080,
+                    "socks5",
+                    i as f64,
+                    "us",
+                    "elite",
+                    0.9,
+                )
+            })
+            .collect();
+        let d =
+            build_chain_decision_with_strategy("high", &pool, &[], &pool, &opts, &LowestLatency(2))
+                .unwrap();
+        let ips: Vec<&str> = d.chain.iter().map(|h| h.ip.as_str()).collect();
+        assert_eq!(ips, vec!["10.0.1.1", "10.0.2.1", "10.0.9.1"]);
+    }
+
+    #[test]
+    fn test_exit_set_matches_canonical_ipv6() {
+        let mut exit_record = make_dns_proxy("2001:db8::1", 1080, "socks5", 0.9);
+        exit_record.country = "de".to_string();
+        let mut dns: Vec<Proxy> = (1..=4)
+            .map(|i| make_dns_proxy(&format!("10.0.{}.1", i), 1080, "socks5", 0.9))
+            .collect();
+        dns.push(exit_record);
+        let opts = ChainOptions {
+            exit_set: parse_exit_set("[2001:DB8::1]:1080").unwrap(),
+            ..ChainOptions::default()
+        };
+
+        for _ in 0..20 {
+            let d = build_chain_decision_with_options("high", &dns, &[], &dns, &opts).unwrap();
+            let (exit, rest) = d.chain.split_last().unwrap();
+            // The pool record is found despite the different spelling...
+            assert_eq!(exit.country, "de");
+            // ...and the exit is kept out of the entry and middle positions
+            assert!(rest.iter().all(|h| h.ip.starts_with("10.")));
+        }
+    }
+
+    #[test]
+    fn test_chain_build_failure_counter() {
+        let before = chain_build_failures();
+
+        let empty: Vec<Proxy> = vec![];
+        assert!(build_chain_decision("phantom", &empty, &empty, &empty).is_err());
+
+        // Other tests run concurrently against the same global counters
+        let after = chain_build_failures();
+        assert!(after.empty_pool > before.empty_pool);
+        assert!(after.total > before.total);
+        assert_eq!(after.total, after.empty_pool + after.unsatisfiable);
     }
 
     #[test]
@@ -913,6 +2624,7 @@ mod tests {
             avg_latency: 100.0,
             min_score: 0.5,
             max_score: 0.9,
+            known_good: false,
         };
 
         let decision = reconstruct_decision_from_topology(&topology, master_secret);
@@ -929,6 +2641,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_degraded_pool_falls_back_to_known_good() {
+        // Too few proxies for phantom's three hops: a fresh rotation fails
+        let dns = vec![
+            make_dns_proxy("192.168.1.1", 8080, "https", 0.8),
+            make_dns_proxy("192.168.2.1", 8081, "socks5", 0.8),
+        ];
+        let non_dns: Vec<Proxy> = vec![];
+        let combined = dns.clone();
+        assert!(build_chain_decision("phantom", &dns, &non_dns, &combined).is_err());
+
+        let mut topology = ChainTopology {
+            chain_id: "known-good-chain".to_string(),
+            hops: vec![
+                HopInfo {
+                    ip: "192.168.1.1".to_string(),
+                    port: 8080,
+                    proto: "https".to_string(),
+                },
+                HopInfo {
+                    ip: "192.168.2.1".to_string(),
+                    port: 8081,
+                    proto: "socks5".to_string(),
+                },
+            ],
+            created_at: 1234567890,
+            mode: "phantom".to_string(),
+            avg_latency: 100.0,
+            min_score: 0.8,
+            max_score: 0.9,
+            known_good: false,
+        };
+        let opts = ChainOptions::default();
+
+        // Without the marker the topology is never used
+        assert!(decision_from_known_good(&topology, &combined, &opts).is_none());
+
+        topology.known_good = true;
+        let decision = decision_from_known_good(&topology, &combined, &opts);
+        assert!(decision.is_some());
+        let decision = decision.unwrap();
+        assert_eq!(decision.chain_id, "known-good-chain");
+        assert_eq!(decision.chain.len(), 2);
+        assert_eq!(decision.encryption.len(), 2);
+        assert_eq!(decision.chain[0].ip, "192.168.1.1");
+        assert_eq!(decision.chain[1].ip, "192.168.2.1");
+
+        // A hop that dropped out of the pool invalidates the fallback
+        let mut dead = combined.clone();
+        dead[1].alive = false;
+        assert!(decision_from_known_good(&topology, &dead, &opts).is_none());
+    }
+
     #[test]
     fn test_normalize_proto() {
         // Test protocol normalization (internal function via filter_mode_pool)
@@ -937,7 +2702,7 @@ mod tests {
         let combined: Vec<Proxy> = vec![];
 
         let decision = build_chain_decision("lite", &dns, &non_dns, &combined);
-        assert!(decision.is_some());
+        assert!(decision.is_ok());
         let decision = decision.unwrap();
 
         // Protocol should be normalized to lowercase
@@ -958,7 +2723,7 @@ mod tests {
         let combined: Vec<Proxy> = vec![];
 
         let decision = build_chain_decision("high", &dns, &non_dns, &combined);
-        assert!(decision.is_some());
+        assert!(decision.is_ok());
         let decision = decision.unwrap();
 
         // Verify avg_latency is reasonable
@@ -966,6 +2731,12 @@ mod tests {
             decision.avg_latency > 0.0,
             "Average latency should be positive"
         );
+        let hop_sum: f64 = decision.chain.iter().map(|h| h.latency).sum();
+        assert!((decision.total_latency - hop_sum).abs() < 1e-9);
+        assert!(
+            (decision.total_latency - decision.avg_latency * decision.chain.len() as f64).abs()
+                < 1e-9
+        );
 
         // Verify min/max scores are within expected range
         assert!(decision.min_score >= 0.0 && decision.min_score <= 1.0);
@@ -989,7 +2760,18 @@ mod tests {
 
         for seed in 0..20u64 {
             let rng = StdRng::seed_from_u64(seed);
-            let selected = weighted_random_choice(&pool, rng, 3, 1.5);
+            let selected: Vec<usize> = weighted_random_choice(
+                &pool,
+                rng,
+                3,
+                1.5,
+                &ChainOptions::default(),
+                None,
+                now_unix(),
+            )
+            .iter()
+            .map(|p| p.index)
+            .collect();
             assert_eq!(selected.len(), 3, "Should select 3 proxies");
 
             // Verify no duplicates in single selection
@@ -1028,6 +2810,570 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ip_subnet_key() {
+        assert_eq!(ip_subnet_key("10.1.2.3").as_deref(), Some("10.1.2.0/24"));
+        assert_eq!(ip_subnet_key("10.1.2.200"), ip_subnet_key("10.1.2.3"));
+        assert_eq!(
+            ip_subnet_key("2001:db8:abcd:12::1").as_deref(),
+            Some("2001:db8:abcd::/48")
+        );
+        assert_eq!(
+            ip_subnet_key("[2001:db8:abcd:ffff::2]"),
+            ip_subnet_key("2001:db8:abcd::1")
+        );
+        assert_eq!(ip_subnet_key("::ffff:10.1.2.3"), ip_subnet_key("10.1.2.3"));
+        assert_eq!(ip_subnet_key("proxy.example.com"), None);
+    }
+
+    #[test]
+    fn test_ipv6_subnet_diversity_and_opt_out() {
+        // Two /48s, three proxies in the first
+        let pool = vec![
+            make_proxy("2001:db8:1::1", 80, "socks5", 100.0, "us", "elite", 0.9),
+            make_proxy("2001:db8:1::2", 80, "socks5", 100.0, "us", "elite", 0.9),
+            make_proxy("2001:db8:1:ff::3", 80, "socks5", 100.0, "us", "elite", 0.9),
+            make_proxy("2001:db8:2::1", 80, "socks5", 100.0, "us", "elite", 0.9),
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        let opts = ChainOptions::default();
+        for _ in 0..20 {
+            let selected = weighted_random_choice(&pool, &mut rng, 2, 1.0, &opts, None, now_unix());
+            let subnets: std::collections::HashSet<_> = selected
+                .iter()
+                .map(|p| ip_subnet_key(&pool[p.index].ip))
+                .collect();
+            assert_eq!(subnets.len(), 2, "hops should span both /48s");
+        }
+
+        // A pool too small for distinct subnets still fills the chain
+        let selected =
+            weighted_random_choice(&pool[..3], &mut rng, 3, 1.0, &opts, None, now_unix());
+        assert_eq!(selected.len(), 3);
+
+        // With the constraint off, same-/48 pairs become possible
+        let loose = ChainOptions {
+            distinct_subnets: false,
+            ..ChainOptions::default()
+        };
+        let same_subnet = (0..200).any(|_| {
+            let s = weighted_random_choice(&pool, &mut rng, 2, 1.0, &loose, None, now_unix());
+            ip_subnet_key(&pool[s[0].index].ip) == ip_subnet_key(&pool[s[1].index].ip)
+        });
+        assert!(same_subnet);
+    }
+
+    /// Always the `n` lowest-latency proxies, fastest first.
+    struct LowestLatency(usize);
+
+    impl ChainStrategy for LowestLatency {
+        fn select(
+            &self,
+            _mode: &str,
+            pool: &[Proxy],
+            _opts: &ChainOptions,
+            _pinned_exit: Option<&Proxy>,
+            _now: u64,
+            _rng: &mut dyn RngCore,
+        ) -> Option<ChainSelection> {
+            let mut idx: Vec<usize> = (0..pool.len()).collect();
+            idx.sort_by(|&a, &b| pool[a].latency.partial_cmp(&pool[b].latency).unwrap());
+            idx.truncate(self.0);
+            Some(idx.into())
+        }
+    }
+
+    #[test]
+    fn test_custom_chain_strategy() {
+        let pool = vec![
+            make_proxy("10.0.1.1", 1080, "socks5", 0.9, "us", "elite", 0.9),
+            make_proxy("10.0.2.1", 1080, "socks5", 0.1, "us", "elite", 0.2),
+            make_proxy("10.0.3.1", 1080, "socks5", 0.5, "us", "elite", 0.5),
+            make_proxy("10.0.4.1", 1080, "socks5", 0.3, "us", "elite", 0.7),
+        ];
+        let opts = ChainOptions::default();
+        let d =
+            build_chain_decision_with_strategy("high", &pool, &[], &pool, &opts, &LowestLatency(3))
+                .unwrap();
+        let ips: Vec<&str> = d.chain.iter().map(|h| h.ip.as_str()).collect();
+        assert_eq!(ips, vec!["10.0.2.1", "10.0.4.1", "10.0.3.1"]);
+        assert_eq!(d.encryption.len(), 3);
+
+        // Fewer hops than the mode's minimum is rejected
+        let err =
+            build_chain_decision_with_strategy("high", &pool, &[], &pool, &opts, &LowestLatency(1))
+                .unwrap_err();
+        assert!(matches!(err, ChainError::NoSelection { .. }));
+    }
+
+    #[test]
+    fn test_hop_count_scales_with_pool_tier() {
+        let pool_of = |score: f64| -> Vec<Proxy> {
+            (1..=8)
+                .map(|i| {
+                    make_proxy(
+                        &format!("10.0.{}.1", i),
+                        1080,
+                        "socks5",
+                        0.2,
+                        "us",
+                        "elite",
+                        score,
+                    )
+                })
+                .collect()
+        };
+        let platinum = pool_of(0.95);
+        let bronze = pool_of(0.35);
+        let silver = pool_of(0.6);
+
+        for _ in 0..30 {
+            let d = build_chain_decision("phantom", &platinum, &[], &platinum).unwrap();
+            assert_eq!(d.chain.len(), 5, "platinum pool takes the upper bound");
+            let d = build_chain_decision("high", &platinum, &[], &platinum).unwrap();
+            assert_eq!(d.chain.len(), 3);
+            let d = build_chain_decision("high", &bronze, &[], &bronze).unwrap();
+            assert_eq!(d.chain.len(), 2, "bronze pool keeps the lower bound");
+        }
+
+        // A middling pool spreads over the range
+        let lens: std::collections::HashSet<usize> = (0..100)
+            .map(|_| {
+                build_chain_decision("phantom", &silver, &[], &silver)
+                    .unwrap()
+                    .chain
+                    .len()
+            })
+            .collect();
+        assert!(lens.len() > 1);
+        assert!(lens.iter().all(|n| (3..=5).contains(n)));
+
+        // An unpolished pool (no tier, no score) is not pinned to the minimum
+        let untiered: Vec<Proxy> = pool_of(0.0)
+            .into_iter()
+            .map(|mut p| {
+                p.tier = ProxyTier::default();
+                p
+            })
+            .collect();
+        let mut rng = StdRng::seed_from_u64(7);
+        let lens: std::collections::HashSet<usize> = (0..100)
+            .map(|_| tier_scaled_hops(&untiered, 2, 3, &mut rng))
+            .collect();
+        assert_eq!(lens, [2, 3].into_iter().collect());
+    }
+
+    #[test]
+    fn test_degrade_to_lite() {
+        // A lone SOCKS4 proxy only satisfies lite mode
+        let pool = vec![make_proxy(
+            "10.0.1.1", 1080, "socks4", 0.2, "us", "elite", 0.9,
+        )];
+        let opts = ChainOptions::default();
+        assert!(build_chain_decision_with_options("phantom", &[], &[], &pool, &opts).is_err());
+
+        let d = build_chain_decision_degrading("phantom", &[], &[], &pool, &opts).unwrap();
+        assert_eq!(d.mode, "lite");
+        assert_eq!(d.chain[0].ip, "10.0.1.1");
+
+        // Nothing to degrade to: the requested mode's error comes back
+        let err = build_chain_decision_degrading("high", &[], &[], &[], &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ChainError::EmptyPool {
+                mode: "high".to_string()
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_min_dns_hops_in_lite_chain() {
+        let mut pool: Vec<Proxy> = (1..=9)
+            .map(|i| {
+                make_proxy(
+                    &format!("10.0.{}.1", i),
+                    8080,
+                    "http",
+                    0.1,
+                    "us",
+                    "elite",
+                    0.95,
+                )
+            })
+            .collect();
+        // A single, low-scoring DNS-capable proxy the sampler would rarely pick
+        pool.push(make_proxy(
+            "10.0.99.1",
+            1080,
+            "socks5",
+            2.0,
+            "us",
+            "elite",
+            0.05,
+        ));
+        let opts = ChainOptions {
+            min_dns_hops: 1,
+            ..ChainOptions::default()
+        };
+        for _ in 0..50 {
+            let d = build_chain_decision_with_options("lite", &[], &[], &pool, &opts).unwrap();
+            assert_eq!(d.chain.len(), 1);
+            assert_eq!(d.chain[0].proto, "socks5");
+        }
+
+        pool.pop();
+        let err = build_chain_decision_with_options("lite", &[], &[], &pool, &opts).unwrap_err();
+        assert!(matches!(err, ChainError::InsufficientDnsHops { .. }));
+    }
+
+    #[test]
+    fn test_promote_dns_hops_respects_chain_rules() {
+        let pool = vec![
+            make_proxy("10.0.1.1", 8080, "http", 0.1, "us", "elite", 0.9),
+            make_proxy("10.0.2.1", 8080, "http", 0.1, "us", "elite", 0.9),
+            // Best DNS candidate, but shares the entry hop's /24
+            make_proxy("10.0.1.2", 1080, "socks5", 0.1, "us", "elite", 0.9),
+            make_proxy("10.0.3.1", 1080, "socks5", 0.1, "us", "elite", 0.5),
+        ];
+        let opts = ChainOptions::default();
+        let mut selected = vec![0, 1];
+        promote_dns_hops(&pool, &mut selected, 1, &opts, None, now_unix());
+        assert_eq!(selected, vec![0, 3]);
+
+        // A swap that would recreate the previous chain is skipped
+        let opts = ChainOptions {
+            previous_hops: vec!["10.0.1.1:8080".to_string(), "10.0.3.1:1080".to_string()],
+            ..ChainOptions::default()
+        };
+        let mut selected = vec![0, 1];
+        promote_dns_hops(&pool, &mut selected, 1, &opts, None, now_unix());
+        assert_eq!(selected, vec![0, 2]);
+
+        // A DNS-capable pinned exit satisfies the requirement by itself
+        let dns_exit = ChainOptions {
+            exit_set: parse_exit_set("socks5://10.0.9.1:1080").unwrap(),
+            min_dns_hops: 1,
+            ..ChainOptions::default()
+        };
+        let http: Vec<Proxy> = pool[..2].to_vec();
+        for _ in 0..20 {
+            let d =
+                build_chain_decision_with_options("stealth", &[], &[], &http, &dns_exit).unwrap();
+            let (exit, rest) = d.chain.split_last().unwrap();
+            assert_eq!(exit.ip, "10.0.9.1");
+            assert!(rest.iter().all(|h| h.proto == "http"));
+        }
+    }
+
+    #[test]
+    fn test_chain_build_counts_by_mode_and_outcome() {
+        // A mode name no other test uses, so parallel tests don't skew counts
+        let mode = "counter-test";
+        let mut opts = ChainOptions::default();
+        opts.mode_protocols
+            .insert(mode.to_string(), vec!["http".to_string()]);
+        let pool = vec![make_proxy(
+            "10.0.1.1", 8080, "http", 0.2, "us", "elite", 0.9,
+        )];
+        let count = |outcome: BuildOutcome| {
+            chain_build_counts()
+                .into_iter()
+                .find(|c| c.mode == mode && c.outcome == outcome)
+                .map_or(0, |c| c.count)
+        };
+
+        build_chain_decision_with_options(mode, &[], &[], &pool, &opts).unwrap();
+        build_chain_decision_with_options(mode, &[], &[], &pool, &opts).unwrap();
+        build_chain_decision_with_options(mode, &[], &[], &[], &opts).unwrap_err();
+        opts.min_dns_hops = 1;
+        build_chain_decision_with_options(mode, &[], &[], &pool, &opts).unwrap_err();
+
+        assert_eq!(count(BuildOutcome::Success), 2);
+        assert_eq!(count(BuildOutcome::Empty), 1);
+        assert_eq!(count(BuildOutcome::Insufficient), 1);
+    }
+
+    #[test]
+    fn test_log_decision_never_logs_keys() {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone)]
+        struct Capture(Arc<Mutex<Vec<u8>>>);
+        impl Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let pool: Vec<Proxy> = (1..=4)
+            .map(|i| {
+                make_proxy(
+                    &format!("10.0.{}.1", i),
+                    443,
+                    "https",
+                    0.2,
+                    "de",
+                    "elite",
+                    0.9,
+                )
+            })
+            .collect();
+        let d = build_chain_decision("high", &pool, &[], &pool).unwrap();
+        assert!(!d.encryption.is_empty());
+
+        let buf = Capture(Arc::new(Mutex::new(Vec::new())));
+        let writer = buf.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || log_decision(&d));
+
+        let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(events.len(), 1 + d.chain.len());
+        assert_eq!(events[0]["fields"]["chain_id"], d.chain_id.as_str());
+        assert_eq!(events[1]["fields"]["ip"], d.chain[0].ip.as_str());
+        assert_eq!(events[1]["fields"]["tier"], "Platinum");
+        assert!(!out.contains("key_hex") && !out.contains("nonce_hex"));
+        for c in &d.encryption {
+            assert!(!out.contains(&c.key_hex) && !out.contains(&c.nonce_hex));
+        }
+    }
+
+    #[test]
+    fn test_proxy_protocols_normalize_to_themselves() {
+        for proto in PROXY_PROTOCOLS {
+            assert_eq!(normalize_proto(proto), proto);
+            assert_eq!(normalize_proto(&proto.to_uppercase()), proto);
+        }
+        assert_eq!(normalize_proto("SOCKS"), "socks5");
+        assert_eq!(normalize_proto("quic"), "quic");
+    }
+
+    #[test]
+    fn test_phantom_score_floor() {
+        let mut dns: Vec<Proxy> = (1..=5)
+            .map(|i| {
+                make_proxy(
+                    &format!("10.0.{}.1", i),
+                    1080,
+                    "socks5",
+                    0.2,
+                    "us",
+                    "elite",
+                    0.9,
+                )
+            })
+            .collect();
+        dns.push(make_proxy(
+            "10.0.9.1", 1080, "socks5", 0.2, "de", "elite", 0.35,
+        ));
+        let opts = ChainOptions::default();
+        for _ in 0..50 {
+            let d = build_chain_decision_with_options("phantom", &dns, &[], &dns, &opts).unwrap();
+            assert!(d.chain.iter().all(|h| h.ip != "10.0.9.1"));
+            assert!(d.min_score >= mode_score_floor("phantom", &opts));
+        }
+
+        // Only sub-floor proxies: the last-resort fallback must not apply
+        let weak: Vec<Proxy> = (1..=4)
+            .map(|i| {
+                make_proxy(
+                    &format!("10.1.{}.1", i),
+                    1080,
+                    "socks5",
+                    0.2,
+                    "us",
+                    "elite",
+                    0.4,
+                )
+            })
+            .collect();
+        assert!(build_chain_decision_with_options("phantom", &weak, &[], &weak, &opts).is_err());
+
+        let lowered = ChainOptions {
+            mode_score_floors: [("phantom".to_string(), 0.4)].into_iter().collect(),
+            ..ChainOptions::default()
+        };
+        let d = build_chain_decision_with_options("phantom", &weak, &[], &weak, &lowered).unwrap();
+        assert!(d.chain.len() >= 3);
+
+        // The floor ranks by effective score: a failing proxy does not pass it
+        let mut failing = dns.clone();
+        for p in &mut failing[..3] {
+            p.fail_count = 2;
+        }
+        let pool = filter_mode_pool_with_options("phantom", &failing, &[], &failing, &opts);
+        assert_eq!(pool.len(), 2);
+
+        // A pinned exit below the floor is refused, not silently used
+        let pinned = ChainOptions {
+            exit_set: parse_exit_set("10.0.9.1:1080").unwrap(),
+            ..ChainOptions::default()
+        };
+        let err =
+            build_chain_decision_with_options("phantom", &dns, &[], &dns, &pinned).unwrap_err();
+        assert!(matches!(err, ChainError::ExitBelowFloor { .. }));
+
+        // So is a known-good chain whose hops have since dropped below it
+        let mut topology = build_chain_decision_with_options("phantom", &dns, &[], &dns, &opts)
+            .unwrap()
+            .to_chain_topology();
+        topology.known_good = true;
+        assert!(decision_from_known_good(&topology, &dns, &opts).is_some());
+        let degraded: Vec<Proxy> = dns
+            .iter()
+            .cloned()
+            .map(|mut p| {
+                p.score = 0.4;
+                p
+            })
+            .collect();
+        assert!(decision_from_known_good(&topology, &degraded, &opts).is_none());
+    }
+
+    #[test]
+    fn test_mode_ports_filter() {
+        let dns = vec![
+            make_proxy("10.0.1.1", 443, "https", 0.2, "us", "elite", 0.9),
+            make_proxy("10.0.2.1", 1080, "socks5", 0.2, "us", "elite", 0.9),
+            make_proxy("10.0.3.1", 31337, "socks5", 0.2, "us", "elite", 0.9),
+        ];
+        let opts = ChainOptions {
+            mode_ports: [("phantom".to_string(), vec![443, 1080])]
+                .into_iter()
+                .collect(),
+            ..ChainOptions::default()
+        };
+        let ports: Vec<u16> = filter_mode_pool_with_options("phantom", &dns, &[], &dns, &opts)
+            .iter()
+            .map(|p| p.port)
+            .collect();
+        assert_eq!(ports, vec![443, 1080]);
+
+        // Modes without an entry keep every port
+        let lite = filter_mode_pool_with_options("lite", &dns, &[], &dns, &opts);
+        assert!(lite.iter().any(|p| p.port == 31337));
+    }
+
+    #[test]
+    fn test_mode_ports_before_tier_fallbacks() {
+        // Every Gold+ proxy sits on an odd port; Silver ones on allowed ports
+        let mut dns: Vec<Proxy> = (1..=3)
+            .map(|i| make_dns_proxy(&format!("10.0.{}.1", i), 31337, "socks5", 0.9))
+            .collect();
+        dns.extend((4..=6).map(|i| make_dns_proxy(&format!("10.0.{}.1", i), 1080, "socks5", 0.6)));
+        let opts = ChainOptions {
+            mode_ports: [("phantom".to_string(), vec![443, 1080])]
+                .into_iter()
+                .collect(),
+            ..ChainOptions::default()
+        };
+
+        let pool = filter_mode_pool_with_options("phantom", &dns, &[], &dns, &opts);
+        assert_eq!(pool.len(), 3);
+        assert!(pool.iter().all(|p| p.port == 1080));
+        let d = build_chain_decision_with_options("phantom", &dns, &[], &dns, &opts).unwrap();
+        assert!(d.chain.iter().all(|h| h.port == 1080));
+    }
+
+    fn country_pool() -> Vec<Proxy> {
+        ["us", "DE", "nl", "de", "fr", "us"]
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                make_proxy(
+                    &format!("10.0.{}.1", i + 1),
+                    8080,
+                    "http",
+                    0.2,
+                    c,
+                    "elite",
+                    0.9,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_include_country_only() {
+        let pool = country_pool();
+        let opts = ChainOptions {
+            include_countries: vec!["de".to_string()],
+            ..ChainOptions::default()
+        };
+        for _ in 0..30 {
+            let d = build_chain_decision_with_options("lite", &[], &[], &pool, &opts).unwrap();
+            assert!(d.chain.iter().all(|h| h.country.eq_ignore_ascii_case("de")));
+        }
+    }
+
+    #[test]
+    fn test_exclude_country_only() {
+        let pool = country_pool();
+        let opts = ChainOptions {
+            exclude_countries: vec!["US".to_string(), "de".to_string()],
+            ..ChainOptions::default()
+        };
+        for _ in 0..30 {
+            let d = build_chain_decision_with_options("lite", &[], &[], &pool, &opts).unwrap();
+            assert!(d
+                .chain
+                .iter()
+                .all(|h| h.country == "nl" || h.country == "fr"));
+        }
+    }
+
+    #[test]
+    fn test_conflicting_country_filters_empty_pool() {
+        let pool = country_pool();
+        let opts = ChainOptions {
+            include_countries: vec!["fr".to_string()],
+            exclude_countries: vec!["FR".to_string()],
+            ..ChainOptions::default()
+        };
+        let err = build_chain_decision_with_options("lite", &[], &[], &pool, &opts).unwrap_err();
+        assert!(matches!(err, ChainError::CountryFiltered { .. }));
+        let msg = err.to_string();
+        assert!(msg.contains("include=[fr]") && msg.contains("exclude=[FR]"));
+    }
+
+    #[test]
+    fn test_get_only_proxy_never_https_exit() {
+        let mut get_only = make_proxy("10.0.1.1", 8080, "http", 0.1, "us", "elite", 0.99);
+        get_only.supports_connect = false;
+        let pool = vec![
+            get_only,
+            make_proxy("10.0.2.1", 8080, "http", 0.5, "us", "elite", 0.3),
+            make_proxy("10.0.3.1", 8080, "https", 0.5, "us", "elite", 0.3),
+        ];
+        let opts = ChainOptions {
+            require_connect: true,
+            ..ChainOptions::default()
+        };
+        for _ in 0..100 {
+            let d = build_chain_decision_with_options("stealth", &[], &[], &pool, &opts).unwrap();
+            assert_ne!(d.chain.last().unwrap().ip, "10.0.1.1");
+        }
+
+        // Plain-HTTP targets may still exit through it
+        let exits_via_get_only = (0..100).any(|_| {
+            let d = build_chain_decision("lite", &[], &[], &pool).unwrap();
+            d.chain.last().unwrap().ip == "10.0.1.1"
+        });
+        assert!(exits_via_get_only);
+    }
+
     #[test]
     fn test_cidr_diversity() {
         // Setup a pool with multiple proxies in the same subnet
@@ -1043,11 +3389,19 @@ mod tests {
         
         // Request 3 hops. With diversity, it MUST pick from different subnets if possible.
         // There are 3 distinct /24 subnets: 1.1.1.x, 2.2.2.x, 3.3.3.x.
-        let selected = weighted_random_choice(&pool, &mut rng, 3, 1.0);
-        
+        let selected = weighted_random_choice(
+            &pool,
+            &mut rng,
+            3,
+            1.0,
+            &ChainOptions::default(),
+            None,
+            now_unix(),
+        );
+
         let mut subnets = std::collections::HashSet::new();
-        for idx in selected {
-            let subnet = get_subnet(&pool[idx].ip);
+        for pick in selected {
+            let subnet = ip_subnet_key(&pool[pick.index].ip).unwrap();
             assert!(subnets.insert(subnet), "Should have picked distinct subnets");
         }
         assert_eq!(subnets.len(), 3);
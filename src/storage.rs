@@ -0,0 +1,135 @@
+//! # Spectre Storage - Pool File I/O
+//!
+//! This module reads and writes pool files, plain or zstd-compressed. The format
+//! is chosen by extension: `proxies_combined.json.zst` is compressed, anything
+//! else is plain JSON. Loaders asked for `name.json` fall back to a sibling
+//! `name.json.zst`, so callers keep using the plain names.
+//!
+//! The Go orchestrator reads only the plain `proxies_*.json` files, so a
+//! compressed write always keeps the plain file next to the `.zst` copy.
+
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Compression level for written pools; zstd's default trades well for JSON.
+const ZSTD_LEVEL: i32 = 3;
+
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+/// `path` with `.zst` appended (`proxies_dns.json` -> `proxies_dns.json.zst`).
+pub fn compressed_sibling(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".zst");
+    PathBuf::from(name)
+}
+
+/// The file to read for `path`: `path` itself if it exists, else its `.zst`
+/// sibling if that exists, else `None`.
+pub fn resolve_pool_path(path: &Path) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path.to_path_buf());
+    }
+    if is_compressed(path) {
+        return None;
+    }
+    let zst = compressed_sibling(path);
+    zst.exists().then_some(zst)
+}
+
+/// Open a pool file for streaming, decompressing `.zst` files on the fly.
+pub fn open_pool_reader(path: &Path) -> io::Result<Box<dyn Read>> {
+    let file = fs::File::open(path)?;
+    if is_compressed(path) {
+        Ok(Box::new(zstd::Decoder::new(file)?))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Read a whole pool file as text, decompressing `.zst` files.
+pub fn read_pool_file(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    open_pool_reader(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Write `contents` to `path`, plus a zstd-compressed copy at its `.zst`
+/// sibling when `compress` is set. The plain file is always written, since the
+/// Go loader cannot read `.zst`; without `compress` a stale `.zst` copy is
+/// removed so the two can never disagree. Returns the compressed path when one
+/// was written, else `path`.
+pub fn write_pool_file(path: &Path, contents: &[u8], compress: bool) -> io::Result<PathBuf> {
+    fs::write(path, contents)?;
+    let zst = compressed_sibling(path);
+    if compress {
+        fs::write(&zst, zstd::encode_all(contents, ZSTD_LEVEL)?)?;
+        return Ok(zst);
+    }
+    match fs::remove_file(&zst) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{parse_proxy_list, Proxy};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spectre_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_compressed_pool_round_trip() {
+        let dir = temp_dir("storage_round_trip");
+        let pool: Vec<Proxy> = (1..=50)
+            .map(|i| {
+                serde_json::from_value(serde_json::json!({
+                    "ip": format!("10.0.{}.1", i),
+                    "port": 8080,
+                    "type": "socks5",
+                    "score": 0.5,
+                }))
+                .unwrap()
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&pool).unwrap();
+        let plain = dir.join("proxies_combined.json");
+
+        // A compressed write refreshes the plain pool the Go side reads too
+        fs::write(&plain, "[]").unwrap();
+        let written = write_pool_file(&plain, json.as_bytes(), true).unwrap();
+        assert_eq!(written, compressed_sibling(&plain));
+        assert_eq!(fs::read_to_string(&plain).unwrap(), json);
+        assert!(fs::metadata(&written).unwrap().len() < json.len() as u64);
+
+        let loaded = parse_proxy_list(&read_pool_file(&written).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&pool).unwrap()
+        );
+
+        // Writing plain again removes the compressed copy
+        write_pool_file(&plain, json.as_bytes(), false).unwrap();
+        assert!(!written.exists());
+        assert_eq!(resolve_pool_path(&plain).unwrap(), plain);
+        assert_eq!(read_pool_file(&plain).unwrap(), json);
+
+        // A lone compressed pool (e.g. copied from elsewhere) is still found
+        fs::rename(&plain, dir.join("moved.json")).unwrap();
+        fs::write(
+            &written,
+            zstd::encode_all(json.as_bytes(), ZSTD_LEVEL).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(resolve_pool_path(&plain).unwrap(), written);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
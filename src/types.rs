@@ -1,8 +1,11 @@
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Proxy quality tier based on real connectivity testing
 /// Higher tiers = better quality, faster, more reliable
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize, Hash, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum ProxyTier {
     /// Dead or very slow (>3s latency, fails CONNECT)
@@ -10,6 +13,7 @@ pub enum ProxyTier {
     Dead = 0,
     /// Working but slow (1-3s latency, some failures)
     #[serde(rename = "bronze")]
+    #[default]
     Bronze = 1,
     /// Good quality (0.5-1s latency, reliable)
     #[serde(rename = "silver")]
@@ -22,12 +26,6 @@ pub enum ProxyTier {
     Platinum = 4,
 }
 
-impl Default for ProxyTier {
-    fn default() -> Self {
-        ProxyTier::Bronze
-    }
-}
-
 /// Custom deserializer for ProxyTier that handles empty strings, missing values, and Option types
 fn deserialize_tier<'de, D>(deserializer: D) -> Result<ProxyTier, D::Error>
 where
@@ -76,6 +74,25 @@ impl ProxyTier {
     }
 }
 
+impl std::str::FromStr for ProxyTier {
+    type Err = String;
+
+    /// Parse a tier name as written in pool files, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "platinum" => Ok(ProxyTier::Platinum),
+            "gold" => Ok(ProxyTier::Gold),
+            "silver" => Ok(ProxyTier::Silver),
+            "bronze" => Ok(ProxyTier::Bronze),
+            "dead" => Ok(ProxyTier::Dead),
+            other => Err(format!(
+                "unknown tier '{}' (expected platinum, gold, silver, bronze or dead)",
+                other
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScoringWeights {
     #[serde(default = "default_weight_lat")]
@@ -88,13 +105,44 @@ pub struct ScoringWeights {
     pub protocol: f64,
     #[serde(default = "default_weight_premium")]
     pub premium: f64,
+    /// Penalty for latency jitter relative to the noisiest proxy in the pool.
+    /// Disabled (0.0) unless explicitly configured.
+    #[serde(default)]
+    pub jitter: f64,
+    /// Bonus for lifetime uptime (`Proxy::uptime`). Disabled (0.0) unless
+    /// explicitly configured.
+    #[serde(default)]
+    pub uptime: f64,
+    /// Two-letter country code of the client's region, in the pools'
+    /// convention (`uk` for the United Kingdom; ISO `gb` is accepted too).
+    /// Proxies in or next to it get a small latency bonus, since their RTT to
+    /// the region is likely lower.
+    #[serde(default)]
+    pub home_country: Option<String>,
+    /// Half-life of the age decay in `Proxy::effective_score_at`. The stored
+    /// `score` is never decayed. 0 disables decay.
+    #[serde(default = "default_decay_half_life")]
+    pub decay_half_life_secs: u64,
 }
 
-fn default_weight_lat() -> f64 { 0.4 }
-fn default_weight_anon() -> f64 { 0.3 }
-fn default_weight_country() -> f64 { 0.2 }
-fn default_weight_proto() -> f64 { 0.1 }
-fn default_weight_premium() -> f64 { 0.5 }
+fn default_weight_lat() -> f64 {
+    0.4
+}
+fn default_weight_anon() -> f64 {
+    0.3
+}
+fn default_weight_country() -> f64 {
+    0.2
+}
+fn default_weight_proto() -> f64 {
+    0.1
+}
+fn default_weight_premium() -> f64 {
+    0.5
+}
+fn default_decay_half_life() -> u64 {
+    DEFAULT_DECAY_HALF_LIFE_SECS
+}
 
 impl Default for ScoringWeights {
     fn default() -> Self {
@@ -104,6 +152,123 @@ impl Default for ScoringWeights {
             country: default_weight_country(),
             protocol: default_weight_proto(),
             premium: default_weight_premium(),
+            jitter: 0.0,
+            uptime: 0.0,
+            home_country: None,
+            decay_half_life_secs: default_decay_half_life(),
+        }
+    }
+}
+
+/// Optional knobs for chain construction. `Default` reproduces the behaviour of
+/// `rotator::build_chain_decision`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainOptions {
+    /// Generate per-hop key/nonce material. When false the decision carries an
+    /// empty `encryption` vector and the tunnel pipes plaintext.
+    #[serde(default = "default_with_encryption")]
+    pub with_encryption: bool,
+    /// Per-mode override of the accepted protocols, e.g. `{"high": ["socks5",
+    /// "https", "socks4"]}` to opt SOCKS4 into high mode. Modes without an
+    /// entry keep their built-in protocol set.
+    #[serde(default)]
+    pub mode_protocols: HashMap<String, Vec<String>>,
+    /// Per-mode override of the minimum score every hop must meet, e.g.
+    /// `{"phantom": 0.6}`. Modes without an entry keep their built-in floor
+    /// (`rotator::mode_score_floor`).
+    #[serde(default)]
+    pub mode_score_floors: HashMap<String, f64>,
+    /// Per-mode allowed proxy ports, e.g. `{"phantom": [443, 1080], "stealth":
+    /// [80, 443, 8080]}`, for networks that flag proxies on unusual ports.
+    /// Modes without an entry (by default, all of them) accept any port.
+    #[serde(default)]
+    pub mode_ports: HashMap<String, Vec<u16>>,
+    /// `ip:port` keys of the previous rotation's hops. The chooser reshuffles
+    /// (a bounded number of times) rather than hand out the same hop set twice
+    /// in a row.
+    #[serde(default)]
+    pub previous_hops: Vec<String>,
+    /// Fixed set of exits. When non-empty the exit hop is always taken from
+    /// this set, at position `exit_index % exit_set.len()`; entry and middle
+    /// hops still come from the pool.
+    #[serde(default)]
+    pub exit_set: Vec<HopInfo>,
+    /// Round-robin position into `exit_set` for this rotation.
+    #[serde(default)]
+    pub exit_index: usize,
+    /// Selection multipliers for the exit hop keyed by `ip:port`, e.g. from
+    /// `affinity::TargetAffinity::exit_weights` for the requested host.
+    /// Proxies without an entry weigh 1.0.
+    #[serde(default)]
+    pub exit_weights: HashMap<String, f64>,
+    /// Avoid two hops in the same /24 (IPv4) or /48 (IPv6), which are likely
+    /// the same operator. Relaxed automatically when the pool is too small.
+    #[serde(default = "default_distinct_subnets")]
+    pub distinct_subnets: bool,
+    /// At least this many hops must be DNS-capable (see
+    /// `Proxy::is_dns_capable`) so domain targets resolve remotely. Capped at
+    /// the chain length.
+    #[serde(default)]
+    pub min_dns_hops: usize,
+    /// The target needs a CONNECT tunnel (e.g. port 443): proxies tagged
+    /// GET-only (`supports_connect == false`) are never picked as the exit.
+    #[serde(default)]
+    pub require_connect: bool,
+    /// When non-empty, only proxies in these countries (case-insensitive) are
+    /// eligible for any hop.
+    #[serde(default)]
+    pub include_countries: Vec<String>,
+    /// Proxies in these countries (case-insensitive) are never used. Takes
+    /// precedence over `include_countries`.
+    #[serde(default)]
+    pub exclude_countries: Vec<String>,
+    /// Scoring parameters selection honours, currently the age-decay
+    /// half-life behind every `Proxy::effective_score_at` ranking and floor.
+    #[serde(default)]
+    pub scoring: ScoringWeights,
+}
+
+impl ChainOptions {
+    /// Whether a proxy located in `country` passes the country filters. An
+    /// unknown (empty) country fails a non-empty include list.
+    pub fn country_allowed(&self, country: &str) -> bool {
+        let is = |c: &String| c.eq_ignore_ascii_case(country);
+        if self.exclude_countries.iter().any(is) {
+            return false;
+        }
+        self.include_countries.is_empty() || self.include_countries.iter().any(is)
+    }
+
+    pub fn has_country_filter(&self) -> bool {
+        !self.include_countries.is_empty() || !self.exclude_countries.is_empty()
+    }
+}
+
+fn default_with_encryption() -> bool {
+    true
+}
+
+fn default_distinct_subnets() -> bool {
+    true
+}
+
+impl Default for ChainOptions {
+    fn default() -> Self {
+        ChainOptions {
+            with_encryption: default_with_encryption(),
+            mode_protocols: HashMap::new(),
+            mode_score_floors: HashMap::new(),
+            mode_ports: HashMap::new(),
+            previous_hops: Vec::new(),
+            exit_set: Vec::new(),
+            exit_index: 0,
+            exit_weights: HashMap::new(),
+            distinct_subnets: default_distinct_subnets(),
+            min_dns_hops: 0,
+            require_connect: false,
+            include_countries: Vec::new(),
+            exclude_countries: Vec::new(),
+            scoring: ScoringWeights::default(),
         }
     }
 }
@@ -118,6 +283,9 @@ pub struct Proxy {
     pub proto: String,
     #[serde(default)]
     pub latency: f64,
+    /// Standard deviation of latency across the last multi-probe verification
+    #[serde(default)]
+    pub jitter: f64,
     #[serde(default)]
     pub country: String,
     #[serde(default)]
@@ -139,6 +307,16 @@ pub struct Proxy {
     /// Source of the proxy: "standard" or "premium"
     #[serde(default = "default_source_type")]
     pub source_type: String,
+    /// Verification probes over the proxy's lifetime in the pool
+    #[serde(default)]
+    pub probes_total: u64,
+    /// Successful probes over the proxy's lifetime in the pool
+    #[serde(default)]
+    pub probes_ok: u64,
+    /// False for HTTP proxies the verifier found to forward plain GET requests
+    /// but reject CONNECT; such proxies cannot carry HTTPS to the target.
+    #[serde(default = "default_supports_connect")]
+    pub supports_connect: bool,
 }
 
 fn default_source_type() -> String {
@@ -149,10 +327,223 @@ fn default_alive() -> bool {
     true
 }
 
+fn default_supports_connect() -> bool {
+    true
+}
+
+/// Canonical `host:port` key for a proxy endpoint, used for deduplication and
+/// hop identity. IP addresses are normalized through `IpAddr` (so `::01` and
+/// `::1`, or `2001:DB8::1` and `2001:db8::1`, share a key) and IPv6 is
+/// bracketed (`[::1]:8080`); hostnames are used verbatim.
+pub fn endpoint_key(ip: &str, port: u16) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V6(v6)) => format!("[{}]:{}", v6, port),
+        _ => format!("{}:{}", canonical_host(ip), port),
+    }
+}
+
+/// The host part of `endpoint_key`, unbracketed: IP addresses in their
+/// canonical text form, hostnames verbatim.
+pub fn canonical_host(ip: &str) -> String {
+    ip.parse::<IpAddr>()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| ip.to_string())
+}
+
 impl Proxy {
+    /// See `endpoint_key`.
     pub fn key(&self) -> String {
-        format!("{}:{}", self.ip, self.port)
+        endpoint_key(&self.ip, self.port)
+    }
+
+    /// Whether the proxy can resolve hostnames on our behalf (SOCKS5, HTTPS).
+    pub fn is_dns_capable(&self) -> bool {
+        matches!(self.proto.to_lowercase().as_str(), "https" | "socks5")
+    }
+
+    /// Whether the record can be dialled at all: `ip` is an IP address or a
+    /// syntactically valid hostname, and `port` is non-zero.
+    pub fn is_valid(&self) -> bool {
+        self.port != 0 && (self.ip.parse::<IpAddr>().is_ok() || is_valid_hostname(&self.ip))
+    }
+
+    /// Record one verification probe: updates the lifetime counters as well as
+    /// `alive`, `fail_count` and `last_verified`.
+    pub fn record_probe(&mut self, ok: bool, now: u64) {
+        self.probes_total += 1;
+        if ok {
+            self.probes_ok += 1;
+            self.fail_count = 0;
+        } else {
+            self.fail_count += 1;
+        }
+        self.alive = ok;
+        self.last_verified = now;
+    }
+
+    /// Lifetime uptime as a fraction of successful probes (0.0-1.0), or `None`
+    /// before the first probe.
+    pub fn uptime(&self) -> Option<f64> {
+        if self.probes_total == 0 {
+            return None;
+        }
+        Some(self.probes_ok.min(self.probes_total) as f64 / self.probes_total as f64)
+    }
+
+    /// `effective_score_at` under the default `ScoringWeights`, as of now.
+    pub fn effective_score(&self) -> f64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.effective_score_at(&ScoringWeights::default(), now)
+    }
+
+    /// Selection weight: the static `score` scaled by live reliability and
+    /// freshness. Every selection path (weighted sampler, mode score floors)
+    /// ranks proxies by this rather than the raw score.
+    ///
+    /// - Each consecutive verification failure divides the weight further
+    /// - A proxy whose last probe failed keeps only a tenth of its weight
+    /// - Age decay per `freshness`, with `weights.decay_half_life_secs`
+    ///
+    /// `now` is taken by the caller so a whole chain build ranks its
+    /// candidates against one clock reading.
+    pub fn effective_score_at(&self, weights: &ScoringWeights, now: u64) -> f64 {
+        let reliability = 1.0 / (1.0 + self.fail_count as f64);
+        let liveness = if self.alive { 1.0 } else { 0.1 };
+        let freshness = self.freshness(weights.decay_half_life_secs, now);
+        self.score * reliability * liveness * freshness
+    }
+
+    /// Age-decay factor for a verification made at `last_verified`: halving
+    /// every `half_life_secs`, floored at `MIN_DECAY_FACTOR`. Never-verified
+    /// proxies (`last_verified == 0`) and a `last_verified` too far in the
+    /// future (see `age_since`) are maximally stale. A zero half-life
+    /// disables decay.
+    pub fn freshness(&self, half_life_secs: u64, now: u64) -> f64 {
+        if half_life_secs == 0 {
+            return 1.0;
+        }
+        if self.last_verified == 0 {
+            return MIN_DECAY_FACTOR;
+        }
+        match age_since(self.last_verified, now) {
+            Some(age) => 0.5_f64
+                .powf(age as f64 / half_life_secs as f64)
+                .max(MIN_DECAY_FACTOR),
+            None => MIN_DECAY_FACTOR,
+        }
+    }
+}
+
+/// Parse the contents of a pool file. The top level is normally an array, but a
+/// lone object (a common slip when hand-editing) is read as a one-proxy pool,
+/// and blank input as an empty one.
+pub fn parse_proxy_list(raw: &str) -> serde_json::Result<Vec<Proxy>> {
+    let raw = raw.trim_start();
+    if raw.is_empty() {
+        Ok(Vec::new())
+    } else if raw.starts_with('{') {
+        serde_json::from_str::<Proxy>(raw).map(|p| vec![p])
+    } else {
+        serde_json::from_str(raw)
+    }
+}
+
+/// Parse scraper output in whichever shape the scraper emitted: a JSON array,
+/// an object wrapping it as `{"proxies": [...]}`, a single proxy object, or
+/// newline-delimited JSON with one proxy per line. NDJSON lines that are not
+/// proxies are skipped; the second value is how many were. Errors only when
+/// the output is none of these shapes, i.e. no NDJSON line parses either.
+pub fn parse_scraper_output(raw: &str) -> serde_json::Result<(Vec<Proxy>, usize)> {
+    #[derive(Deserialize)]
+    struct Wrapped {
+        proxies: Vec<Proxy>,
+    }
+
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    if let Ok(proxies) = serde_json::from_str::<Vec<Proxy>>(trimmed) {
+        return Ok((proxies, 0));
     }
+    if let Ok(wrapped) = serde_json::from_str::<Wrapped>(trimmed) {
+        return Ok((wrapped.proxies, 0));
+    }
+    if let Ok(proxy) = serde_json::from_str::<Proxy>(trimmed) {
+        return Ok((vec![proxy], 0));
+    }
+
+    let mut proxies = Vec::new();
+    let mut skipped = 0;
+    let mut first_err = None;
+    for line in trimmed.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match serde_json::from_str::<Proxy>(line) {
+            Ok(p) => proxies.push(p),
+            Err(e) => {
+                skipped += 1;
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    match first_err {
+        Some(e) if proxies.is_empty() => Err(e),
+        _ => Ok((proxies, skipped)),
+    }
+}
+
+/// Future timestamps within this window are treated as clock jitter (age 0).
+pub const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+/// Default `ScoringWeights::decay_half_life_secs`: half weight at 24h.
+pub const DEFAULT_DECAY_HALF_LIFE_SECS: u64 = 24 * 3600;
+
+/// Lowest freshness factor age decay reaches.
+pub const MIN_DECAY_FACTOR: f64 = 0.1;
+
+/// Seconds elapsed from `timestamp` to `now`, robust to non-monotonic clocks.
+///
+/// A timestamp slightly ahead of `now` (within `MAX_CLOCK_SKEW_SECS`) is
+/// clamped to age 0. Anything further in the future means either the recording
+/// clock ran ahead or ours jumped backward (NTP correction, VM resume); the age
+/// is then unknown, so this returns `None` and callers must treat the record
+/// as stale rather than fresh. This sits on the selection hot path and never
+/// logs; `polish::warn_clock_skew` reports such records once per load.
+pub fn age_since(timestamp: u64, now: u64) -> Option<u64> {
+    if timestamp <= now {
+        return Some(now - timestamp);
+    }
+    (timestamp - now <= MAX_CLOCK_SKEW_SECS).then_some(0)
+}
+
+/// RFC 1123 hostname check: dot-separated labels of 1-63 alphanumerics or
+/// hyphens, not starting or ending with a hyphen, at most 253 chars overall.
+/// A proxy host must be fully qualified, so single-label names other than
+/// `localhost` (e.g. a garbled `not-an-ip` field) are rejected.
+fn is_valid_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    if host.is_empty() || host.len() > 253 {
+        return false;
+    }
+    if !host.contains('.') && !host.eq_ignore_ascii_case("localhost") {
+        return false;
+    }
+    // All-numeric dotted names are malformed IPs, not hostnames
+    if host
+        .split('.')
+        .all(|l| l.chars().all(|c| c.is_ascii_digit()))
+    {
+        return false;
+    }
+    host.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -162,6 +553,7 @@ pub struct PolishResult {
     pub combined: Vec<Proxy>,
 }
 
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all, module = "rotator_rs"))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainHop {
     pub ip: String,
@@ -174,6 +566,7 @@ pub struct ChainHop {
     pub obfuscation: Option<ObfuscationConfig>,
 }
 
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all, module = "rotator_rs"))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObfuscationConfig {
     #[serde(default = "default_obfuscation_mode")]
@@ -202,12 +595,14 @@ fn default_padding_range() -> (u32, u32) {
     (0, 0)
 }
 
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all, module = "rotator_rs"))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CryptoHop {
     pub key_hex: String,
     pub nonce_hex: String,
 }
 
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all, module = "rotator_rs"))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RotationDecision {
     pub mode: String,
@@ -215,11 +610,45 @@ pub struct RotationDecision {
     pub chain_id: String,
     pub chain: Vec<ChainHop>,
     pub avg_latency: f64,
+    /// Sum of hop latencies: the expected end-to-end cost of the serial chain
+    #[serde(default)]
+    pub total_latency: f64,
     pub min_score: f64,
     pub max_score: f64,
     pub encryption: Vec<CryptoHop>,
     #[serde(default)]
     pub garlic: bool,
+    /// How the chooser picked the hops; absent on decisions it did not build
+    /// (known-good fallbacks, replayed topologies)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<SelectionTrace>,
+}
+
+/// Record of how `rotator::build_chain_decision*` built a chain, rendered by
+/// `rotator::explain_decision`.
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all, module = "rotator_rs"))]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SelectionTrace {
+    /// Proxies in the mode pool the hops were drawn from
+    pub pool: usize,
+    /// Chain-wide rules that were applied: pool sources and fallbacks, score
+    /// floor, exit pinning, previous-chain avoidance
+    pub rules: Vec<String>,
+    /// One entry per hop, parallel to `RotationDecision::chain`
+    pub hops: Vec<HopSelection>,
+}
+
+/// How one hop was picked.
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all, module = "rotator_rs"))]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HopSelection {
+    /// Proxies the hop was drawn from once its constraints were applied
+    pub candidates: usize,
+    /// `Proxy::effective_score_at` the hop was ranked by, when recorded
+    #[serde(default)]
+    pub effective_score: Option<f64>,
+    /// Constraints actually applied to this pick
+    pub constraints: Vec<String>,
 }
 
 /// ChainTopology contains only the chain structure without cryptographic material.
@@ -234,6 +663,10 @@ pub struct ChainTopology {
     pub avg_latency: f64,
     pub min_score: f64,
     pub max_score: f64,
+    /// Set only once the chain has successfully served traffic. A known-good
+    /// topology is the fallback when a fresh rotation cannot build a chain.
+    #[serde(default)]
+    pub known_good: bool,
 }
 
 /// HopInfo contains only the network topology information for a chain hop.
@@ -246,6 +679,17 @@ pub struct HopInfo {
     pub proto: String,
 }
 
+/// A RotationDecision whose key material is encrypted at rest.
+/// `decision.encryption` is always empty; the hop keys live in `sealed_keys_hex`,
+/// encrypted with an Argon2id passphrase-derived key. See `crypto::seal_decision`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedDecision {
+    pub decision: RotationDecision,
+    pub salt_hex: String,
+    pub nonce_hex: String,
+    pub sealed_keys_hex: String,
+}
+
 impl RotationDecision {
     /// Converts a RotationDecision to ChainTopology, stripping all encryption keys.
     /// This is the safe version to persist to disk.
@@ -266,6 +710,318 @@ impl RotationDecision {
             avg_latency: self.avg_latency,
             min_score: self.min_score,
             max_score: self.max_score,
+            known_good: false,
+        }
+    }
+
+    /// Re-key the chain in place: a fresh key/nonce for every existing hop,
+    /// keeping the same proxies. Bumps `timestamp`; with `new_chain_id` the
+    /// chain is also re-identified. A plain chain (no `encryption`) stays plain.
+    pub fn rotate_keys(&mut self, new_chain_id: bool) {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::from_entropy();
+        for hop in &mut self.encryption {
+            let (key_hex, nonce_hex) = crate::rotator::generate_key_nonce(&mut rng);
+            *hop = CryptoHop { key_hex, nonce_hex };
+        }
+        if new_chain_id {
+            self.chain_id = crate::rotator::generate_chain_id(&mut rng);
+        }
+        self.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(self.timestamp);
+    }
+
+    /// Structural check for decisions loaded from disk (replays, `--step open`):
+    /// a non-empty chain of dialable hops, and either no key material or one
+    /// well-formed 32-byte key / 12-byte nonce per hop.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chain.is_empty() {
+            return Err("decision has an empty chain".to_string());
+        }
+        for (i, hop) in self.chain.iter().enumerate() {
+            let valid_host = hop.ip.parse::<IpAddr>().is_ok() || is_valid_hostname(&hop.ip);
+            if hop.port == 0 || !valid_host {
+                return Err(format!(
+                    "hop {} has invalid address {}:{}",
+                    i, hop.ip, hop.port
+                ));
+            }
+        }
+        if self.encryption.is_empty() {
+            return Ok(());
+        }
+        if self.encryption.len() != self.chain.len() {
+            return Err(format!(
+                "decision has {} hop keys for {} hops",
+                self.encryption.len(),
+                self.chain.len()
+            ));
+        }
+        let is_hex =
+            |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit());
+        for (i, c) in self.encryption.iter().enumerate() {
+            if !is_hex(&c.key_hex, 64) || !is_hex(&c.nonce_hex, 24) {
+                return Err(format!("hop {} has malformed key material", i));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_proxy(ip: &str, port: u16) -> Proxy {
+        serde_json::from_value(serde_json::json!({
+            "ip": ip,
+            "port": port,
+            "type": "socks5",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_proxy_key_canonical() {
+        assert_eq!(make_proxy("192.168.1.1", 1080).key(), "192.168.1.1:1080");
+        assert_eq!(make_proxy("::1", 8080).key(), "[::1]:8080");
+        assert_eq!(make_proxy("::01", 8080).key(), "[::1]:8080");
+        assert_eq!(
+            make_proxy("2001:DB8::Ff", 1080).key(),
+            "[2001:db8::ff]:1080"
+        );
+        assert_eq!(
+            make_proxy("Proxy.Example.com", 8080).key(),
+            "Proxy.Example.com:8080"
+        );
+    }
+
+    #[test]
+    fn test_proxy_is_valid() {
+        assert!(make_proxy("192.168.1.1", 1080).is_valid());
+        assert!(make_proxy("2001:db8::1", 1080).is_valid());
+        assert!(make_proxy("proxy.example.com", 8080).is_valid());
+
+        assert!(
+            !make_proxy("192.168.1.1", 0).is_valid(),
+            "Port 0 is invalid"
+        );
+        assert!(make_proxy("localhost", 1080).is_valid());
+        assert!(!make_proxy("not-an-ip", 1080).is_valid());
+        assert!(!make_proxy("not-an-ip!", 1080).is_valid());
+        assert!(!make_proxy("999.1.1.1", 1080).is_valid());
+        assert!(!make_proxy("", 1080).is_valid());
+        assert!(!make_proxy("-bad.example.com", 1080).is_valid());
+    }
+
+    #[test]
+    fn test_effective_score_penalizes_failures() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut flaky = make_proxy("192.168.1.1", 1080);
+        flaky.score = 0.9;
+        flaky.fail_count = 4;
+        flaky.last_verified = now;
+
+        let mut steady = make_proxy("192.168.1.2", 1080);
+        steady.score = 0.6;
+        steady.last_verified = now;
+
+        assert!(flaky.effective_score() < steady.effective_score());
+        // A reliable, just-verified proxy keeps its static score
+        assert!((steady.effective_score() - 0.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_record_probe_accumulates_uptime() {
+        let mut p = make_proxy("192.168.1.1", 1080);
+        assert_eq!(p.uptime(), None);
+
+        p.record_probe(true, 100);
+        p.record_probe(true, 200);
+        p.record_probe(false, 300);
+        p.record_probe(true, 400);
+
+        assert_eq!(p.probes_total, 4);
+        assert_eq!(p.probes_ok, 3);
+        assert_eq!(p.uptime(), Some(0.75));
+        assert_eq!(p.last_verified, 400);
+        assert_eq!(p.fail_count, 0);
+        assert!(p.alive);
+
+        p.record_probe(false, 500);
+        assert_eq!(p.fail_count, 1);
+        assert!(!p.alive);
+        assert_eq!(p.uptime(), Some(0.6));
+    }
+
+    #[test]
+    fn test_rotate_keys_keeps_hops() {
+        let pool: Vec<Proxy> = (1..=5)
+            .map(|i| make_proxy(&format!("192.168.{}.1", i), 1080))
+            .collect();
+        let mut d = crate::rotator::build_chain_decision("high", &pool, &[], &pool).unwrap();
+        let before = d.clone();
+
+        d.rotate_keys(false);
+        assert_eq!(d.chain_id, before.chain_id);
+        assert!(d.timestamp >= before.timestamp);
+        assert_eq!(d.chain.len(), before.chain.len());
+        for (new, old) in d.chain.iter().zip(&before.chain) {
+            assert_eq!((&new.ip, new.port), (&old.ip, old.port));
         }
+        assert_eq!(d.encryption.len(), before.encryption.len());
+        for (new, old) in d.encryption.iter().zip(&before.encryption) {
+            assert_ne!(new.key_hex, old.key_hex);
+            assert_ne!(new.nonce_hex, old.nonce_hex);
+        }
+        assert!(d.validate().is_ok());
+
+        d.rotate_keys(true);
+        assert_ne!(d.chain_id, before.chain_id);
+    }
+
+    #[test]
+    fn test_parse_proxy_list_shapes() {
+        let array = r#"[{"ip": "192.168.1.1", "port": 1080, "type": "socks5"},
+                        {"ip": "192.168.1.2", "port": 8080, "type": "http"}]"#;
+        let proxies = parse_proxy_list(array).unwrap();
+        assert_eq!(proxies.len(), 2);
+        assert_eq!(proxies[1].ip, "192.168.1.2");
+
+        let single = r#"  {"ip": "192.168.1.1", "port": 1080, "type": "socks5"}"#;
+        let proxies = parse_proxy_list(single).unwrap();
+        assert_eq!(proxies.len(), 1);
+        assert_eq!(proxies[0].port, 1080);
+
+        assert!(parse_proxy_list("").unwrap().is_empty());
+        assert!(parse_proxy_list(" \n ").unwrap().is_empty());
+        assert!(parse_proxy_list("[]").unwrap().is_empty());
+        assert!(parse_proxy_list(r#""192.168.1.1:1080""#).is_err());
+    }
+
+    #[test]
+    fn test_age_since_handles_clock_skew() {
+        let now = 1_700_000_000;
+        assert_eq!(age_since(now - 60, now), Some(60));
+        // Small drift between clocks is jitter, not staleness
+        assert_eq!(age_since(now + 30, now), Some(0));
+        // A last_verified far in the future has no trustworthy age
+        assert_eq!(age_since(now + 86_400, now), None);
+    }
+
+    #[test]
+    fn test_future_last_verified_is_not_fresh() {
+        let mut p = make_proxy("192.168.1.1", 1080);
+        p.score = 0.8;
+
+        // Our clock jumped backward a day after this proxy was verified
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        p.last_verified = now + 86_400;
+        let skewed = p.effective_score();
+
+        p.last_verified = now;
+        let fresh = p.effective_score();
+
+        assert!(skewed < fresh);
+        assert!((skewed - 0.8 * MIN_DECAY_FACTOR).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_freshness_decays_stale_proxies() {
+        let now = 1_700_000_000;
+        let day = 24 * 3600;
+        let mut p = make_proxy("192.168.1.1", 1080);
+
+        p.last_verified = now;
+        assert_eq!(p.freshness(day, now), 1.0);
+        p.last_verified = now - day;
+        assert!((p.freshness(day, now) - 0.5).abs() < 1e-9);
+        // A week old sinks to the floor; a zero half-life turns decay off
+        p.last_verified = now - 7 * day;
+        assert_eq!(p.freshness(day, now), MIN_DECAY_FACTOR);
+        assert_eq!(p.freshness(0, now), 1.0);
+        // Never verified counts as maximally stale
+        p.last_verified = 0;
+        assert_eq!(p.freshness(day, now), MIN_DECAY_FACTOR);
+    }
+
+    #[test]
+    fn test_never_verified_ranks_below_fresh() {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut fresh = make_proxy("192.168.1.1", 1080);
+        fresh.score = 0.6;
+        fresh.last_verified = wall;
+        let mut two_days = fresh.clone();
+        two_days.last_verified = wall - 2 * 24 * 3600;
+        let mut never = fresh.clone();
+        never.score = 0.9;
+        never.last_verified = 0;
+
+        assert!(never.effective_score() < fresh.effective_score());
+        assert!(never.effective_score() < two_days.effective_score());
+    }
+
+    #[test]
+    fn test_effective_score_decay_is_read_only() {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut fresh = make_proxy("192.168.1.1", 1080);
+        fresh.score = 0.9;
+        fresh.last_verified = wall;
+        let mut stale = fresh.clone();
+        stale.last_verified = wall - 7 * 24 * 3600;
+
+        assert!(stale.effective_score() < fresh.effective_score());
+        assert_eq!(stale.score, 0.9);
+        // Repeated reads do not compound
+        assert_eq!(stale.effective_score(), stale.effective_score());
+
+        let slow = ScoringWeights {
+            decay_half_life_secs: 30 * 24 * 3600,
+            ..ScoringWeights::default()
+        };
+        assert!(stale.effective_score_at(&slow, wall) > stale.effective_score());
+    }
+
+    #[test]
+    fn test_parse_scraper_output_shapes() {
+        let array = r#"[
+            {"ip": "192.168.1.1", "port": 1080, "type": "socks5"},
+            {"ip": "192.168.1.2", "port": 8080, "type": "http"}
+        ]"#;
+        let wrapped = format!(r#"{{"source": "go_scraper", "proxies": {}}}"#, array);
+        let ndjson = "{\"ip\": \"192.168.1.1\", \"port\": 1080, \"type\": \"socks5\"}\n\
+                      scraper: 2 sources done\n\
+                      \n\
+                      {\"ip\": \"192.168.1.2\", \"port\": 8080, \"type\": \"http\"}\n";
+
+        let keys = |raw: &str| {
+            let (proxies, skipped) = parse_scraper_output(raw).unwrap();
+            (proxies.iter().map(Proxy::key).collect::<Vec<_>>(), skipped)
+        };
+        let expected = vec![
+            "192.168.1.1:1080".to_string(),
+            "192.168.1.2:8080".to_string(),
+        ];
+        assert_eq!(keys(array), (expected.clone(), 0));
+        assert_eq!(keys(&wrapped), (expected.clone(), 0));
+        assert_eq!(keys(ndjson), (expected, 1));
+
+        assert_eq!(keys("  \n"), (Vec::new(), 0));
+        assert!(parse_scraper_output("scraper crashed\n").is_err());
     }
 }